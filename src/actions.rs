@@ -0,0 +1,254 @@
+use crossterm::event::KeyCode;
+
+/// everything the user can do, whether or not it has a key bound
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Action {
+    Quit,
+    TogglePause,
+    NextTrack,
+    PrevTrack,
+    Stop,
+    ToggleStopAfterCurrent,
+    CycleSleepTimer,
+    SetSleepTimer,
+    ToggleSleepQuits,
+    NextChapter,
+    PrevChapter,
+    CycleAbLoop,
+    AddBookmark,
+    ShowBookmarks,
+    CycleShuffle,
+    CycleRepeat,
+    ClearQueue,
+    CycleReplayGain,
+    ToggleLoudnorm,
+    ShowEqualizer,
+    SpeedUp,
+    SlowDown,
+    ResetSpeed,
+    AddUrl,
+    NewStationList,
+    Subscribe,
+    NewSmartPlaylist,
+    ShowMaintenance,
+    RefreshFeeds,
+    TogglePlayed,
+    DownloadEpisode,
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    JumpToCurrent,
+    ShowHelp,
+    ShowMessages,
+    ShowLog,
+    OpenPalette,
+}
+
+/// an action along with how it is shown in the help overlay and palette
+#[derive(Debug)]
+pub struct ActionInfo {
+    pub action: Action,
+    pub name: &'static str,
+    pub category: &'static str,
+    pub keys: &'static [KeyCode],
+}
+
+/// every action, in the order they are listed in the help overlay
+pub static ACTIONS: &[ActionInfo] = &[
+    // --- playback ---
+    ActionInfo { action: Action::TogglePause, name: "play/pause", category: "playback", keys: &[KeyCode::Char(' ')] },
+    ActionInfo { action: Action::NextTrack, name: "next track", category: "playback", keys: &[KeyCode::Char('>')] },
+    ActionInfo { action: Action::PrevTrack, name: "previous track", category: "playback", keys: &[KeyCode::Char('<')] },
+    ActionInfo { action: Action::Stop, name: "stop", category: "playback", keys: &[] },
+    ActionInfo { action: Action::ToggleStopAfterCurrent, name: "stop after current track", category: "playback", keys: &[KeyCode::Char('S')] },
+    ActionInfo { action: Action::CycleSleepTimer, name: "cycle sleep timer", category: "playback", keys: &[KeyCode::Char('z')] },
+    ActionInfo { action: Action::SetSleepTimer, name: "set sleep timer", category: "playback", keys: &[] },
+    ActionInfo { action: Action::ToggleSleepQuits, name: "toggle quit on sleep", category: "playback", keys: &[] },
+    ActionInfo { action: Action::NextChapter, name: "next chapter", category: "playback", keys: &[KeyCode::Char('.')] },
+    ActionInfo { action: Action::PrevChapter, name: "previous chapter", category: "playback", keys: &[KeyCode::Char(',')] },
+    ActionInfo { action: Action::CycleAbLoop, name: "set loop a/b", category: "playback", keys: &[KeyCode::Char('b')] },
+    ActionInfo { action: Action::AddBookmark, name: "add bookmark", category: "playback", keys: &[KeyCode::Char('m')] },
+    ActionInfo { action: Action::ShowBookmarks, name: "bookmarks", category: "playback", keys: &[KeyCode::Char('B')] },
+
+    // --- queue ---
+    ActionInfo { action: Action::CycleShuffle, name: "cycle shuffle", category: "queue", keys: &[KeyCode::Char('s')] },
+    ActionInfo { action: Action::CycleRepeat, name: "cycle repeat", category: "queue", keys: &[KeyCode::Char('r')] },
+    ActionInfo { action: Action::ClearQueue, name: "clear queue", category: "queue", keys: &[] },
+
+    // --- audio ---
+    ActionInfo { action: Action::CycleReplayGain, name: "cycle replaygain", category: "audio", keys: &[KeyCode::Char('g')] },
+    ActionInfo { action: Action::ToggleLoudnorm, name: "toggle loudness normalisation", category: "audio", keys: &[KeyCode::Char('n')] },
+    ActionInfo { action: Action::ShowEqualizer, name: "equalizer", category: "audio", keys: &[KeyCode::Char('E')] },
+    ActionInfo { action: Action::SpeedUp, name: "speed up", category: "audio", keys: &[KeyCode::Char(']')] },
+    ActionInfo { action: Action::SlowDown, name: "slow down", category: "audio", keys: &[KeyCode::Char('[')] },
+    ActionInfo { action: Action::ResetSpeed, name: "reset speed", category: "audio", keys: &[KeyCode::Char('=')] },
+
+    // --- library ---
+    ActionInfo { action: Action::AddUrl, name: "add url to playlist", category: "library", keys: &[] },
+    ActionInfo { action: Action::NewStationList, name: "new radio station list", category: "library", keys: &[] },
+    ActionInfo { action: Action::Subscribe, name: "subscribe to podcast", category: "library", keys: &[] },
+    ActionInfo { action: Action::NewSmartPlaylist, name: "new smart playlist", category: "library", keys: &[] },
+    ActionInfo { action: Action::ShowMaintenance, name: "library maintenance", category: "library", keys: &[KeyCode::Char('M')] },
+    ActionInfo { action: Action::RefreshFeeds, name: "check podcasts for new episodes", category: "library", keys: &[] },
+    ActionInfo { action: Action::TogglePlayed, name: "mark episode played", category: "library", keys: &[KeyCode::Char('p')] },
+    ActionInfo { action: Action::DownloadEpisode, name: "download episode", category: "library", keys: &[KeyCode::Char('D')] },
+
+    // --- navigation ---
+    ActionInfo { action: Action::Up, name: "move up", category: "navigation", keys: &[KeyCode::Up, KeyCode::Char('k')] },
+    ActionInfo { action: Action::Down, name: "move down", category: "navigation", keys: &[KeyCode::Down, KeyCode::Char('j')] },
+    ActionInfo { action: Action::Left, name: "previous column", category: "navigation", keys: &[KeyCode::Left, KeyCode::Char('h')] },
+    ActionInfo { action: Action::Right, name: "next column", category: "navigation", keys: &[KeyCode::Right, KeyCode::Char('l')] },
+    ActionInfo { action: Action::Select, name: "play selected", category: "navigation", keys: &[KeyCode::Enter] },
+    ActionInfo { action: Action::JumpToCurrent, name: "jump to current track", category: "navigation", keys: &[] },
+
+    // --- general ---
+    ActionInfo { action: Action::ShowHelp, name: "show help", category: "general", keys: &[KeyCode::Char('?')] },
+    ActionInfo { action: Action::ShowMessages, name: "show messages", category: "general", keys: &[KeyCode::Char('e')] },
+    ActionInfo { action: Action::ShowLog, name: "show log", category: "general", keys: &[KeyCode::Char('L')] },
+    ActionInfo { action: Action::OpenPalette, name: "command palette", category: "general", keys: &[KeyCode::Char(':')] },
+    ActionInfo { action: Action::Quit, name: "quit", category: "general", keys: &[KeyCode::Char('q')] },
+];
+
+/// gets the action bound to a key
+/// 
+/// # arguments
+/// * 'key' - the key that was pressed
+/// 
+/// # returns
+/// * the bound action, or None if the key is unbound
+pub fn keytoaction(key: KeyCode) -> Option<Action> {
+    ACTIONS.iter().find(|info| info.keys.contains(&key)).map(|info| info.action)
+}
+
+/// gets a printable name for a key
+pub fn keyname(key: &KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => String::from("space"),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Up => String::from("up"),
+        KeyCode::Down => String::from("down"),
+        KeyCode::Left => String::from("left"),
+        KeyCode::Right => String::from("right"),
+        KeyCode::Enter => String::from("enter"),
+        KeyCode::Esc => String::from("esc"),
+        KeyCode::Tab => String::from("tab"),
+        KeyCode::Backspace => String::from("backspace"),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
+
+/// builds the lines of the help overlay, grouped by category
+/// 
+/// # returns
+/// * the lines as a Vec<String>
+pub fn helplines() -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut lastcategory = "";
+
+    for info in ACTIONS {
+        if info.category != lastcategory {
+            if !lines.is_empty() {
+                lines.push(String::new());
+            }
+            lines.push(format!("── {} ──", info.category));
+            lastcategory = info.category;
+        }
+
+        let keys = if info.keys.is_empty() {
+            String::from(":")
+        } else {
+            info.keys.iter().map(keyname).collect::<Vec<String>>().join(" / ")
+        };
+        lines.push(format!("{:<24}{:>14}", info.name, keys));
+    }
+
+    lines
+}
+
+/// scores how well a query fuzzy matches a name
+/// 
+/// every character of the query has to appear in the name in order.
+/// consecutive characters and characters at the start of a word score higher
+/// 
+/// # arguments
+/// * 'query' - what the user typed
+/// * 'name' - the action name to match against
+/// 
+/// # returns
+/// * the score, or None if the query does not match
+pub fn fuzzyscore(query: &str, name: &str) -> Option<i32> {
+    let query: Vec<char> = query.to_lowercase().chars().filter(|c| !c.is_whitespace()).collect();
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+
+    let mut score = 0;
+    let mut queryidx = 0;
+    let mut lastmatch: Option<usize> = None;
+
+    for (nameidx, c) in name.iter().enumerate() {
+        if queryidx >= query.len() {
+            break;
+        }
+        if *c == query[queryidx] {
+            score += 1;
+            if lastmatch == Some(nameidx.wrapping_sub(1)) {
+                score += 5; // consecutive
+            }
+            if nameidx == 0 || !name[nameidx - 1].is_alphanumeric() {
+                score += 3; // start of a word
+            }
+            lastmatch = Some(nameidx);
+            queryidx += 1;
+        }
+    }
+
+    if queryidx < query.len() {
+        return None;
+    }
+
+    // prefer shorter names when scores tie
+    Some(score * 100 - name.len() as i32)
+}
+
+/// gets the actions matching a query, best match first
+/// 
+/// # arguments
+/// * 'query' - what the user typed into the palette
+/// 
+/// # returns
+/// * the matching actions
+pub fn matchactions(query: &str) -> Vec<&'static ActionInfo> {
+    if query.trim().is_empty() {
+        return ACTIONS.iter().filter(|info| info.action != Action::OpenPalette).collect();
+    }
+
+    let mut matches: Vec<(i32, &'static ActionInfo)> = ACTIONS
+        .iter()
+        .filter(|info| info.action != Action::OpenPalette)
+        .filter_map(|info| fuzzyscore(query, info.name).map(|score| (score, info)))
+        .collect();
+
+    matches.sort_by_key(|m| std::cmp::Reverse(m.0));
+    matches.into_iter().map(|(_, info)| info).collect()
+}
+
+/// gets an action by its name, used for commands sent by other instances
+pub fn actionbyname(name: &str) -> Option<Action> {
+    ACTIONS.iter().find(|info| info.name == name).map(|info| info.action)
+}
+
+/// gets the name of an action
+pub fn actionname(action: Action) -> &'static str {
+    ACTIONS.iter().find(|info| info.action == action).map(|info| info.name).unwrap_or("")
+}
+
+/// checks if an action makes sense to send to another instance
+pub fn isremote(action: Action) -> bool {
+    matches!(
+        action,
+        Action::TogglePause | Action::NextTrack | Action::PrevTrack | Action::Stop | Action::ToggleStopAfterCurrent
+            | Action::CycleSleepTimer | Action::ToggleSleepQuits | Action::NextChapter | Action::PrevChapter | Action::CycleAbLoop | Action::CycleShuffle | Action::CycleRepeat
+            | Action::CycleReplayGain | Action::ToggleLoudnorm | Action::SpeedUp | Action::SlowDown | Action::ResetSpeed
+    )
+}
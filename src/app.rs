@@ -1,12 +1,15 @@
 use anyhow::Result;
 use crossterm::event::KeyCode;
 
-use crate::consts::{App, CurrentColumn, LOCKPATH};
-use crate::backend;
-use std::fs::OpenOptions;
-use std::io::Write;
-use std::process;
-use fs4::fs_std::FileExt;
+use crate::actions::{self, Action};
+use crate::consts::{
+    App, BookmarkListState, CurrentColumn, EqualizerState, MaintenanceState, PaletteState, Playlist, PlaylistKind, PromptKind, RepeatType, ShuffleMode,
+    SourceKind, StatusLevel, Track
+};
+use crate::error::BackendError;
+use crate::{art, audio, backend, bookmarks, chapters, config, constructors, crossfade, feeds, instance, logging, maintenance, metadata, sleep, smart, sponsorblock, stream, supervisor};
+
+static SPEEDSTEP: f64 = 0.1;    // how much [ and ] change the speed by
 
 pub fn getnextidx(currentopt: Option<usize>, listlen: usize) -> usize {
     if listlen == 0 {
@@ -41,39 +44,33 @@ pub fn getprevidx(currentopt: Option<usize>, listlen: usize) -> usize {
 }
 
 pub fn firstrun(app: &mut App) -> Result<()> {
-    let file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(false)
-        .open(LOCKPATH)?;
-
-    match file.try_lock_exclusive() {
-        Ok(_) => {
-            file.set_len(0)?;
-            writeln!(&file, "{}", process::id())?;
+    match instance::acquirelock()? {
+        Some(file) => {
             app.lockfile = Some(file);
-            println!("Instance {}: Lock acquired successfully.", std::process::id());
+            log::info!("instance {}: lock acquired", std::process::id());
 
             app.repeatedinstance = false;
+            app.control = instance::listen().ok();
 
-            // --- initial app state setup ---
+            // --- initial app state setup, smart playlists are not saved with their tracks ---
+            smart::refresh(app);
             if !app.playlists.is_empty() {
-                app.playliststate.select(Some(0));
+                app.ui.playliststate.select(Some(0));
                 if !app.playlists[0].tracks.is_empty() {
-                    app.tracksstate.select(Some(0));
+                    app.ui.tracksstate.select(Some(0));
                 } else {
-                    app.tracksstate.select(None);
+                    app.ui.tracksstate.select(None);
                 }
             } else {
-                app.playliststate.select(None);
-                app.tracksstate.select(None);
+                app.ui.playliststate.select(None);
+                app.ui.tracksstate.select(None);
             }
-            app.queuestate.select(None);
+            app.ui.queuestate.select(None);
 
             Ok(())
         }
-        Err(_) => {
-            println!("Instance {}: Failed to acquire lock (already held?).", std::process::id()); // Debug
+        None => {
+            log::info!("instance {}: lock already held, controlling the running instance", std::process::id());
             app.repeatedinstance = true;
             app.lockfile = None;
 
@@ -83,20 +80,58 @@ pub fn firstrun(app: &mut App) -> Result<()> {
 }
 
 pub fn ontick(app: &mut App, counter: &u8) -> Result<()> {
-    if let Some(child) = &mut app.mpv {
-        if let Ok(Some(_)) = child.try_wait() {
-            // mpv died
-            if app.playing {
-                backend::playcurrenttrack(app)?;
-            }
+    supervisor::check(app)?;
+    sleep::check(app)?;
+
+    // --- run commands sent by other instances ---
+    let commands = app.control.as_ref().map(instance::pollcommands).unwrap_or_default();
+    for command in commands {
+        match actions::actionbyname(&command) {
+            Some(action) => runaction(app, action)?,
+            None => app.status.push(StatusLevel::Error, format!("another instance sent an unknown command '{}'", command)),
         }
     }
 
+    metadata::update(app);
+    sponsorblock::update(app);
+    feeds::update(app);
+    maintenance::update(app);
+    art::update(&mut app.ui.art, app.queue.get(app.currentqueueidx as usize));
+
+    // every tick, a second is too coarse for a smooth fade
+    if let Err(e) = backend::fade(app) {
+        log::debug!("fade skipped: {}", e);
+    }
+    if let Err(e) = crossfade::update(app) {
+        log::debug!("could not fade out the previous track: {}", e);
+    }
+
     if counter == &3 { // every second
+        // --- keep the player one track ahead for gapless playback ---
+        if app.preloadpending {
+            backend::preloadnext(app);
+        }
+        if let Err(e) = crossfade::check(app) {
+            log::debug!("could not start a crossfade: {}", e);
+        }
+        if let Err(e) = backend::checkadvance(app) {
+            log::debug!("could not check for a gapless transition: {}", e);
+        }
+        if let Err(e) = chapters::update(app) {
+            log::debug!("could not get chapters: {}", e);
+        }
+        if let Err(e) = stream::update(app) {
+            log::debug!("could not get stream metadata: {}", e);
+        }
+        backend::rememberposition(app);
+
         if app.playing {
             // get current position from MPV instead of incrementing our own counter
-            if let Ok(mpvduration) = backend::getplaybackpos(crate::consts::MPVSOCKET) {
+            if let Ok(mpvduration) = app.player.position() {
                 app.currentdurationsecs = mpvduration;
+                if let Err(e) = sponsorblock::skip(app) {
+                    log::debug!("could not skip a segment: {}", e);
+                }
                 
                 // check if we've reached the end of the track
                 let queueidx = app.currentqueueidx as usize;
@@ -105,9 +140,13 @@ pub fn ontick(app: &mut App, counter: &u8) -> Result<()> {
                     let trackduration = queuevec[queueidx].duration;
                     // if we're near the end of the track, play the next one
                     // use a small buffer (1 second) to ensure we change tracks before the end
-                    if mpvduration >= trackduration.saturating_sub(1) {
+                    // a duration of 0 means it is not known yet, then mpv saying it reached the end is the only sign
+                    let nearend = trackduration > 0 && mpvduration >= trackduration.saturating_sub(1);
+                    let eof = app.player.getproperty("eof-reached").ok().and_then(|v| v.as_bool()).unwrap_or(false);
+                    // a preloaded track plays by itself
+                    if (nearend || eof) && app.preloadedidx.is_none() {
                         app.currentdurationsecs = 0;
-                        backend::playnexttrack(app)?;
+                        backend::trackended(app)?;
                     }
                 }
             } else {
@@ -116,11 +155,9 @@ pub fn ontick(app: &mut App, counter: &u8) -> Result<()> {
                 // also check for end of track in fallback mode
                 let queueidx = app.currentqueueidx as usize;
                 let queuevec = &app.queue;
-                if queuevec.len() > queueidx {
-                    if app.currentdurationsecs > queuevec[queueidx].duration {
-                        app.currentdurationsecs = 0;
-                        backend::playnexttrack(app)?;
-                    }
+                if queuevec.len() > queueidx && queuevec[queueidx].duration > 0 && app.currentdurationsecs > queuevec[queueidx].duration {
+                    app.currentdurationsecs = 0;
+                    backend::trackended(app)?;
                 }
             }
         }
@@ -130,70 +167,443 @@ pub fn ontick(app: &mut App, counter: &u8) -> Result<()> {
 }
 
 pub fn onkey(app: &mut App, key: KeyCode) -> Result<()> {
+    // if the command palette is open it gets every key
+    if app.ui.palette.onscreen {
+        return onpalettekey(app, key);
+    }
+
+    // same for the equalizer and bookmarks
+    if app.ui.equalizer.onscreen {
+        return onequalizerkey(app, key);
+    }
+    if app.ui.bookmarks.onscreen {
+        return onbookmarkkey(app, key);
+    }
+    if app.ui.maintenance.onscreen {
+        return onmaintenancekey(app, key);
+    }
+
     // if a popup is on screen
-    if app.popup.onscreen {
-        if key == KeyCode::Enter {
-            app.popup.onscreen = false;
-            if app.popup.dangerous {
-                app.running = false;
+    if app.ui.popup.onscreen {
+        match key {
+            KeyCode::Enter | KeyCode::Esc | KeyCode::Char('?') => {
+                let dangerous = app.ui.popup.dangerous;
+                constructors::clearpopup(app);
+                if dangerous {
+                    app.running = false;
+                }
             }
-            return Ok(());
+            KeyCode::Up | KeyCode::Char('k') => app.ui.popup.scroll = app.ui.popup.scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                let maxscroll = app.ui.popup.message.len().saturating_sub(1) as u16;
+                app.ui.popup.scroll = std::cmp::min(app.ui.popup.scroll + 1, maxscroll);
+            }
+            _ => {}
         }
         return Ok(());
     }
 
-    // if this is a repeated instance, only allow enter to close
+    // if this is a repeated instance, keys control the running instance
     if app.repeatedinstance {
-        if key == KeyCode::Enter {
-            app.running = false;
-            return Ok(());
+        match actions::keytoaction(key) {
+            Some(Action::Select | Action::Quit) => app.running = false,
+            Some(action) if actions::isremote(action) => {
+                let name = actions::actionname(action);
+                app.ui.remotestatus = match instance::sendcommand(name) {
+                    Ok(_) => format!("sent '{}'", name),
+                    Err(e) => format!("failed to send '{}': {}", name, e),
+                };
+            }
+            _ => {}
         }
         return Ok(());
     }
 
+    if let Some(action) = actions::keytoaction(key) {
+        runaction(app, action)?;
+    }
+    Ok(())
+}
+
+/// handles a key while the command palette is open
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'key' - the key that was pressed
+/// 
+/// # returns
+/// * none
+pub fn onpalettekey(app: &mut App, key: KeyCode) -> Result<()> {
+    match key {
+        KeyCode::Esc => app.ui.palette = PaletteState::default(),
+        KeyCode::Enter if app.ui.palette.prompt.is_some() => {
+            let input = app.ui.palette.input.trim().to_string();
+            let prompt = app.ui.palette.prompt;
+            app.ui.palette = PaletteState::default();
+            if let (Some(prompt), false) = (prompt, input.is_empty()) {
+                runprompt(app, prompt, &input)?;
+            }
+        }
+        KeyCode::Up | KeyCode::Down | KeyCode::Tab if app.ui.palette.prompt.is_some() => {}
+        KeyCode::Enter => {
+            let matches = actions::matchactions(&app.ui.palette.input);
+            let selected = matches.get(app.ui.palette.selected).map(|info| info.action);
+            app.ui.palette = PaletteState::default();
+            if let Some(action) = selected {
+                runaction(app, action)?;
+            }
+        }
+        KeyCode::Up => {
+            let matchcount = actions::matchactions(&app.ui.palette.input).len();
+            app.ui.palette.selected = getprevidx(Some(app.ui.palette.selected), matchcount);
+        }
+        KeyCode::Down | KeyCode::Tab => {
+            let matchcount = actions::matchactions(&app.ui.palette.input).len();
+            app.ui.palette.selected = getnextidx(Some(app.ui.palette.selected), matchcount);
+        }
+        KeyCode::Backspace => {
+            if app.ui.palette.input.pop().is_none() {
+                // backspace on an empty palette closes it, like vim
+                app.ui.palette = PaletteState::default();
+            }
+            app.ui.palette.selected = 0;
+        }
+        KeyCode::Char(c) => {
+            app.ui.palette.input.push(c);
+            app.ui.palette.selected = 0;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// handles a key while the equalizer is open
+/// 
+/// moving through the presets previews them, enter keeps the selected one and esc goes back
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'key' - the key that was pressed
+/// 
+/// # returns
+/// * none
+pub fn onequalizerkey(app: &mut App, key: KeyCode) -> Result<()> {
+    let presets = config::eqpresets(&app.config);
+    match key {
+        KeyCode::Esc | KeyCode::Char('E') => {
+            let previous = app.ui.equalizer.previous.clone();
+            app.ui.equalizer = EqualizerState::default();
+            audio::seteq(app, &previous, false)?;
+        }
+        KeyCode::Enter => {
+            app.ui.equalizer = EqualizerState::default();
+            let name = app.config.eq.clone();
+            audio::seteq(app, &name, true)?;
+            app.status.push(StatusLevel::Info, format!("equalizer set to {}", name));
+        }
+        KeyCode::Up | KeyCode::Char('k') | KeyCode::Down | KeyCode::Char('j') => {
+            let selected = if matches!(key, KeyCode::Up | KeyCode::Char('k')) {
+                getprevidx(Some(app.ui.equalizer.selected), presets.len())
+            } else {
+                getnextidx(Some(app.ui.equalizer.selected), presets.len())
+            };
+            app.ui.equalizer.selected = selected;
+            audio::seteq(app, &presets[selected].name, false)?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// handles a key while the maintenance view is open
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'key' - the key that was pressed
+/// 
+/// # returns
+/// * none
+pub fn onmaintenancekey(app: &mut App, key: KeyCode) -> Result<()> {
+    // removing in bulk needs the same key pressed twice, any other key cancels it
+    let confirming = app.ui.maintenance.confirming.take();
+    match key {
+        KeyCode::Esc | KeyCode::Char('M') => app.ui.maintenance = MaintenanceState::default(),
+        KeyCode::Char('c') => maintenance::checklinks(app),
+        KeyCode::Char(c @ ('d' | 'D')) if confirming == Some(c) => {
+            let removed = maintenance::dedupe(&mut app.playlists, c == 'D');
+            smart::refresh(app);
+            maintenance::refreshreport(app);
+            app.status.push(StatusLevel::Info, format!("removed {} duplicates", removed));
+        }
+        KeyCode::Char('x') if confirming == Some('x') => {
+            let removed = maintenance::removedead(app);
+            smart::refresh(app);
+            maintenance::refreshreport(app);
+            app.status.push(StatusLevel::Info, format!("removed {} dead tracks", removed));
+        }
+        KeyCode::Char(c @ ('d' | 'D' | 'x')) => app.ui.maintenance.confirming = Some(c),
+        KeyCode::Up | KeyCode::Char('k') => app.ui.maintenance.scroll = app.ui.maintenance.scroll.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => {
+            let maxscroll = app.ui.maintenance.report.len().saturating_sub(1) as u16;
+            app.ui.maintenance.scroll = std::cmp::min(app.ui.maintenance.scroll.saturating_add(1), maxscroll);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// PRIVATE function that gets the track selected in the focused column, or the current one
+fn selectedtrack(app: &App) -> Option<Track> {
+    match app.ui.currentcolumn {
+        CurrentColumn::Tracks => app.playlists
+            .get(app.ui.playliststate.selected().unwrap_or(0))
+            .and_then(|p| p.tracks.get(app.ui.tracksstate.selected()?)),
+        CurrentColumn::Queue => app.queue.get(app.ui.queuestate.selected()?),
+        CurrentColumn::Playlists => app.queue.get(app.currentqueueidx as usize),
+    }.cloned()
+}
+
+/// handles a key while the bookmark list is open
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'key' - the key that was pressed
+/// 
+/// # returns
+/// * none
+pub fn onbookmarkkey(app: &mut App, key: KeyCode) -> Result<()> {
+    let count = app.queue.get(app.currentqueueidx as usize).map(|t| t.bookmarks.len()).unwrap_or(0);
+    let selected = app.ui.bookmarks.selected;
     match key {
+        KeyCode::Esc | KeyCode::Char('B') => app.ui.bookmarks = BookmarkListState::default(),
+        KeyCode::Enter => {
+            app.ui.bookmarks = BookmarkListState::default();
+            bookmarks::jumptobookmark(app, selected)?;
+        }
+        KeyCode::Char('d') => {
+            bookmarks::removebookmark(app, selected);
+            app.ui.bookmarks.selected = selected.min(count.saturating_sub(2));
+        }
+        KeyCode::Up | KeyCode::Char('k') => app.ui.bookmarks.selected = getprevidx(Some(selected), count),
+        KeyCode::Down | KeyCode::Char('j') => app.ui.bookmarks.selected = getnextidx(Some(selected), count),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// reports an error without quitting
+/// 
+/// errors that leave the app useless get a popup that quits when closed,
+/// everything else goes to the status line
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'error' - the error
+/// 
+/// # returns
+/// * none
+pub fn reporterror(app: &mut App, error: anyhow::Error) {
+    let fatal = error.downcast_ref::<BackendError>().is_some_and(|e| e.isfatal());
+    log::error!("{:#}", error);
+    if fatal {
+        constructors::showpopup(app, String::from(" error "), vec![error.to_string(), String::new(), String::from("press enter to quit")], true);
+    } else {
+        app.status.push(StatusLevel::Error, error.to_string());
+    }
+}
+
+/// opens the palette to ask for text
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'prompt' - what the text is for
+/// 
+/// # returns
+/// * none
+pub fn openprompt(app: &mut App, prompt: PromptKind) {
+    app.ui.palette = PaletteState { onscreen: true, prompt: Some(prompt), ..PaletteState::default() };
+}
+
+/// handles the text entered into a prompt
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'prompt' - what the text is for
+/// * 'input' - the text, already trimmed and not empty
+/// 
+/// # returns
+/// * none
+pub fn runprompt(app: &mut App, prompt: PromptKind, input: &str) -> Result<()> {
+    log::debug!("prompt {:?}: '{}'", prompt, input);
+    match prompt {
+        PromptKind::AddUrl => {
+            let playlistidx = app.ui.playliststate.selected().unwrap_or(0);
+            if app.playlists.get(playlistidx).is_some_and(|p| p.kind == PlaylistKind::Smart) {
+                return Err(anyhow::anyhow!("smart playlists fill themselves, add the url to another playlist"));
+            }
+            if playlistidx < app.playlists.len() {
+                // title, artist and duration are filled in by the resolver
+                let mut track = Track::new("", "", 0, input);
+                if app.playlists[playlistidx].kind == PlaylistKind::Stations {
+                    track.source = SourceKind::Stream;
+                }
+                app.playlists[playlistidx].tracks.push(track);
+                metadata::request(app, input);
+                smart::refresh(app);
+                let name = app.playlists[playlistidx].name.clone();
+                app.status.push(StatusLevel::Info, format!("added to {}, looking up its details", name));
+            }
+        }
+        PromptKind::SleepTimer => match sleep::parseminutes(input) {
+            Some(minutes) => sleep::start(app, minutes),
+            None => return Err(anyhow::anyhow!("'{}' is not a sleep timer length, try 45, 1:30 or off", input)),
+        },
+        PromptKind::Bookmark(position) => bookmarks::addbookmark(app, input, position),
+        PromptKind::Subscribe => feeds::subscribe(app, input)?,
+        PromptKind::NewSmartPlaylist => {
+            let Some((name, query)) = input.split_once(':') else {
+                return Err(anyhow::anyhow!("name the playlist first, like 'short ones: length < 3:00'"));
+            };
+            smart::create(app, name.trim(), query)?;
+            app.ui.playliststate.select(Some(app.playlists.len() - 1));
+            let count = app.playlists[app.playlists.len() - 1].tracks.len();
+            app.status.push(StatusLevel::Info, format!("created {} with {} tracks", name.trim(), count));
+        }
+        PromptKind::NewStationList => {
+            app.playlists.push(Playlist { name: String::from(input), kind: PlaylistKind::Stations, ..Playlist::default() });
+            app.ui.playliststate.select(Some(app.playlists.len() - 1));
+            app.status.push(StatusLevel::Info, format!("created {}, add stations to it with :add url to playlist", input));
+        }
+    }
+    Ok(())
+}
+
+/// runs an action, whether it came from a key or the command palette
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'action' - the action to run
+/// 
+/// # returns
+/// * none
+pub fn runaction(app: &mut App, action: Action) -> Result<()> {
+    log::debug!("action {:?}", action);
+    match action {
         // --- controls ---
-        KeyCode::Char('q') => app.running = false,
-        KeyCode::Char(' ') => backend::togglepause(app)?,
-        KeyCode::Char('>') => backend::playnexttrack(app)?,
-        KeyCode::Char('<') => backend::playprevtrack(app)?,
-        KeyCode::Char('s') => backend::toggleshuffle(app)?,
-        KeyCode::Char('r') => backend::cyclerepeat(app)?,
-        
+        Action::Quit => app.running = false,
+        Action::TogglePause => backend::togglepause(app)?,
+        Action::NextTrack => backend::playnexttrack(app)?,
+        Action::ToggleStopAfterCurrent => {
+            backend::togglestopaftercurrent(app);
+            let state = if app.stopaftercurrent { "on" } else { "off" };
+            app.status.push(StatusLevel::Info, format!("stop after current track {}", state));
+        }
+        Action::CycleSleepTimer => sleep::cyclepreset(app),
+        Action::SetSleepTimer => openprompt(app, PromptKind::SleepTimer),
+        Action::ToggleSleepQuits => {
+            app.config.sleepquits = !app.config.sleepquits;
+            let state = if app.config.sleepquits { "quits" } else { "pauses" };
+            app.status.push(StatusLevel::Info, format!("sleep timer {} when it goes off", state));
+        }
+        Action::PrevTrack => backend::playprevtrack(app)?,
+        Action::Stop => backend::stop(app)?,
+        Action::CycleShuffle => backend::cycleshuffle(app)?,
+        Action::CycleRepeat => backend::cyclerepeat(app)?,
+        Action::CycleReplayGain => audio::cyclereplaygain(app)?,
+        Action::ToggleLoudnorm => audio::toggleloudnorm(app)?,
+        Action::ShowEqualizer => {
+            let presets = config::eqpresets(&app.config);
+            let selected = presets.iter().position(|p| p.name == app.config.eq).unwrap_or(0);
+            app.ui.equalizer = EqualizerState { onscreen: true, selected, previous: app.config.eq.clone() };
+        }
+        Action::SpeedUp => audio::changespeed(app, SPEEDSTEP)?,
+        Action::SlowDown => audio::changespeed(app, -SPEEDSTEP)?,
+        Action::ResetSpeed => audio::changespeed(app, 0.0)?,
+        Action::NextChapter => chapters::nextchapter(app)?,
+        Action::PrevChapter => chapters::prevchapter(app)?,
+        Action::CycleAbLoop => bookmarks::cycleabloop(app)?,
+        Action::AddBookmark => {
+            if app.queue.get(app.currentqueueidx as usize).is_some() {
+                openprompt(app, PromptKind::Bookmark(app.currentdurationsecs));
+            }
+        }
+        Action::ShowBookmarks => app.ui.bookmarks = BookmarkListState { onscreen: true, selected: 0 },
+        Action::ClearQueue => {
+            backend::stop(app)?;
+            app.queue.clear();
+            app.queuebeforeshuffle = None;
+            app.queuebeforerepeat = None;
+            app.currentqueueidx = 0;
+            app.ui.queuestate.select(None);
+        }
+
+        // --- library ---
+        Action::AddUrl => openprompt(app, PromptKind::AddUrl),
+        Action::NewStationList => openprompt(app, PromptKind::NewStationList),
+        Action::Subscribe => openprompt(app, PromptKind::Subscribe),
+        Action::NewSmartPlaylist => openprompt(app, PromptKind::NewSmartPlaylist),
+        Action::ShowMaintenance => {
+            app.ui.maintenance = MaintenanceState { onscreen: true, ..MaintenanceState::default() };
+            maintenance::refreshreport(app);
+        }
+        Action::RefreshFeeds => {
+            feeds::refresh(app);
+            app.status.push(StatusLevel::Info, String::from("checking podcasts for new episodes"));
+        }
+        Action::TogglePlayed => {
+            if let Some(track) = selectedtrack(app).filter(|t| t.isepisode()) {
+                feeds::setplayed(app, &track.id, !track.played);
+            }
+        }
+        Action::DownloadEpisode => {
+            if let Some(track) = selectedtrack(app) {
+                feeds::download(app, &track)?;
+            }
+        }
+
         // --- navigation ---
-        KeyCode::Up | KeyCode::Down | KeyCode::Char('k') | KeyCode::Char('j') => {
-            let isup = key == KeyCode::Up || key == KeyCode::Char('k');
-            handleverticalnavigation(app, isup)?;
-        },
-        KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
-            let isleft = key == KeyCode::Left || key == KeyCode::Char('h');
-            handlehorizontalnavigation(app, isleft)?;
-        },
-        KeyCode::Enter => handleenter(app)?,
-        _ => {}
+        Action::Up | Action::Down => handleverticalnavigation(app, action == Action::Up)?,
+        Action::Left | Action::Right => handlehorizontalnavigation(app, action == Action::Left)?,
+        Action::Select => handleenter(app)?,
+        Action::JumpToCurrent => {
+            if !app.queue.is_empty() {
+                app.ui.currentcolumn = CurrentColumn::Queue;
+                let idxtoselect = std::cmp::min(app.currentqueueidx as usize, app.queue.len() - 1);
+                app.ui.queuestate.select(Some(idxtoselect));
+            }
+        }
+
+        // --- general ---
+        Action::ShowHelp => constructors::showpopup(app, String::from(" help "), actions::helplines(), false),
+        Action::ShowLog => constructors::showpopup(app, String::from(" log "), logging::recentlines(), false),
+        Action::ShowMessages => {
+            let lines = constructors::getstatuslines(&app.status);
+            constructors::showpopup(app, String::from(" messages "), lines, false);
+        }
+        Action::OpenPalette => app.ui.palette = PaletteState { onscreen: true, ..PaletteState::default() },
     }
     Ok(())
 }
 
 // navigation functions
 pub fn handleverticalnavigation(app: &mut App, isup: bool) -> Result<()> {
-    match app.currentcolumn {
+    match app.ui.currentcolumn {
         CurrentColumn::Playlists => {
             let playlists = &app.playlists;
             if !playlists.is_empty() {
-                let currentselection = app.playliststate.selected();
+                let currentselection = app.ui.playliststate.selected();
                 let nextselection = if isup {
                     getprevidx(currentselection, playlists.len())
                 } else {
                     getnextidx(currentselection, playlists.len())
                 };
-                app.playliststate.select(Some(nextselection));
+                app.ui.playliststate.select(Some(nextselection));
             }
         }
         CurrentColumn::Tracks => {
-            let playlistidx = app.playliststate.selected().unwrap_or(0) as usize;
+            let playlistidx = app.ui.playliststate.selected().unwrap_or(0);
             let playlists = &app.playlists;
-            let trackstate = &app.tracksstate;
+            let trackstate = &app.ui.tracksstate;
             // ensure playlist index is valid before accessing tracks
             if playlistidx < playlists.len() {
                 let tracks = &playlists[playlistidx].tracks;
@@ -204,13 +614,13 @@ pub fn handleverticalnavigation(app: &mut App, isup: bool) -> Result<()> {
                     } else {
                         getnextidx(currentselection, tracks.len())
                     };
-                    app.tracksstate.select(Some(nextselection));
+                    app.ui.tracksstate.select(Some(nextselection));
                 }
             }
         }
         CurrentColumn::Queue => {
             let tracks = &app.queue;
-            let queuestate = &app.queuestate;
+            let queuestate = &app.ui.queuestate;
             if !tracks.is_empty() {
                 let currentselection = queuestate.selected();
                 let nextselection = if isup {
@@ -218,7 +628,7 @@ pub fn handleverticalnavigation(app: &mut App, isup: bool) -> Result<()> {
                 } else {
                     getnextidx(currentselection, tracks.len())
                 };
-                app.queuestate.select(Some(nextselection));
+                app.ui.queuestate.select(Some(nextselection));
             }
         }
     }
@@ -227,42 +637,42 @@ pub fn handleverticalnavigation(app: &mut App, isup: bool) -> Result<()> {
 
 pub fn handlehorizontalnavigation(app: &mut App, isleft: bool) -> Result<()> {
     // deselect tracks/queuestate when switching
-    // match app.currentcolumn {
+    // match app.ui.currentcolumn {
     //     CurrentColumn::Playlists => {},
-    //     CurrentColumn::Tracks => app.tracksstate.select(None),
-    //     CurrentColumn::Queue => app.queuestate.select(None),
+    //     CurrentColumn::Tracks => app.ui.tracksstate.select(None),
+    //     CurrentColumn::Queue => app.ui.queuestate.select(None),
     // }
 
     // determine and set the new column
-    app.currentcolumn = match app.currentcolumn {
+    app.ui.currentcolumn = match app.ui.currentcolumn {
         CurrentColumn::Playlists => if isleft { CurrentColumn::Queue } else { CurrentColumn::Tracks },
         CurrentColumn::Tracks => if isleft { CurrentColumn::Playlists } else { CurrentColumn::Queue },
         CurrentColumn::Queue => if isleft { CurrentColumn::Tracks } else { CurrentColumn::Playlists },
     };
 
     // select an item in the newly focused column
-    match app.currentcolumn {
+    match app.ui.currentcolumn {
         CurrentColumn::Playlists => {
             if !app.playlists.is_empty() {
                  // select last known or default to 0
-                let idxtoselect = std::cmp::min(app.playliststate.selected().unwrap_or(0), app.playlists.len() - 1);
-                app.playliststate.select(Some(idxtoselect));
+                let idxtoselect = std::cmp::min(app.ui.playliststate.selected().unwrap_or(0), app.playlists.len() - 1);
+                app.ui.playliststate.select(Some(idxtoselect));
             }
         }
         CurrentColumn::Tracks => {
-            let playlistidx = app.playliststate.selected().unwrap_or(0);
+            let playlistidx = app.ui.playliststate.selected().unwrap_or(0);
              // check if playlist and its tracks are valid before selecting
             if playlistidx < app.playlists.len() && !app.playlists[playlistidx].tracks.is_empty() {
                 // select last known or default to 0
-                let idxtoselect = std::cmp::min(app.tracksstate.selected().unwrap_or(0), app.playlists[playlistidx].tracks.len() - 1);
-                app.tracksstate.select(Some(idxtoselect));
+                let idxtoselect = std::cmp::min(app.ui.tracksstate.selected().unwrap_or(0), app.playlists[playlistidx].tracks.len() - 1);
+                app.ui.tracksstate.select(Some(idxtoselect));
             }
         }
         CurrentColumn::Queue => {
             if !app.queue.is_empty() {
                 // select current playing index or default to 0
                 let idxtoselect = std::cmp::min(app.currentqueueidx as usize, app.queue.len() - 1);
-                app.queuestate.select(Some(idxtoselect));
+                app.ui.queuestate.select(Some(idxtoselect));
             }
         }
     }
@@ -270,27 +680,21 @@ pub fn handlehorizontalnavigation(app: &mut App, isleft: bool) -> Result<()> {
 }
 
 pub fn handleenter(app: &mut App) -> Result<()> {
-    match app.currentcolumn {
+    match app.ui.currentcolumn {
         CurrentColumn::Playlists => {
             // use selected index from state
-            if let Some(selectedidx) = app.playliststate.selected() {
+            if let Some(selectedidx) = app.ui.playliststate.selected() {
                 if selectedidx < app.playlists.len() {
                     app.currentplaylistidx = selectedidx as u32; // update context
-                    app.queue = app.playlists[selectedidx].tracks.clone();
-                    app.currentqueueidx = 0; // start from beginning
-
-                    let startidx = app.tracksstate.selected().unwrap_or(0);
-                    app.currentqueueidx = startidx as u32;
-                    app.queuestate.select(Some(startidx));
-
-                    app.playing = true;
-                    backend::playcurrenttrack(app)?;
+                    let startidx = app.ui.tracksstate.selected().unwrap_or(0);
+                    let tracks = app.playlists[selectedidx].tracks.clone();
+                    startqueue(app, tracks, startidx)?;
                 }
             }
         }
         CurrentColumn::Tracks => {
-            if let Some(selectedtrackidx) = app.tracksstate.selected() {
-                let playlistidx = app.playliststate.selected().unwrap_or(0) as usize;
+            if let Some(selectedtrackidx) = app.ui.tracksstate.selected() {
+                let playlistidx = app.ui.playliststate.selected().unwrap_or(0);
                 // check playlist index validity
                 if playlistidx < app.playlists.len() {
                     let currentplaylist = &app.playlists[playlistidx].tracks;
@@ -298,29 +702,55 @@ pub fn handleenter(app: &mut App) -> Result<()> {
                     if selectedtrackidx < currentplaylist.len() {
                         app.currentplaylistidx = playlistidx as u32;
                         // create queue starting from selected track
-                        app.queue = currentplaylist.clone();
-                        // set current song to selected track
-                        app.currentqueueidx = selectedtrackidx as u32;
-                        app.queuestate.select(Some(selectedtrackidx as usize));
-                        app.playing = true;
-                        backend::playcurrenttrack(app)?;
+                        let tracks = currentplaylist.clone();
+                        startqueue(app, tracks, selectedtrackidx)?;
                     }
                 }
             }
         }
         CurrentColumn::Queue => {
             // use selected index from state
-            if let Some(selected) = app.queuestate.selected() {
+            if let Some(selected) = app.ui.queuestate.selected() {
                 // check queue index validity
                 if selected < app.queue.len() {
-                    app.currentqueueidx = selected as u32; // jump to selected track
-                    app.playing = true;
+                    // jump to selected track, keeping the queue as it is
+                    app.currentqueueidx = selected as u32;
                     backend::playcurrenttrack(app)?;
                 }
             }
         }
     }
-    backend::repeatqueue(app)?;
-    backend::shufflequeue(app)?;
+    Ok(())
+}
+
+/// replaces the queue and plays one of its tracks, then applies shuffle and repeat to it
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'tracks' - the new queue
+/// * 'startidx' - index of the track to play
+/// 
+/// # returns
+/// * none
+pub fn startqueue(app: &mut App, tracks: Vec<Track>, startidx: usize) -> Result<()> {
+    app.queue = tracks;
+    app.queuebeforeshuffle = None;
+    app.queuebeforerepeat = None;
+    app.currentqueueidx = startidx as u32;
+    app.ui.queuestate.select(Some(startidx));
+    backend::playcurrenttrack(app)?;
+
+    // both keep the current track current, so the queue column matches what is playing
+    if app.repeat != RepeatType::None {
+        backend::repeatqueue(app)?;
+    }
+    if app.shuffle != ShuffleMode::Off {
+        // nothing in a new queue has been played, so the chosen track goes first and the rest is shuffled
+        app.queuebeforeshuffle = Some(app.queue.clone());
+        let current = app.queue.remove(app.currentqueueidx as usize);
+        app.queue.insert(0, current);
+        app.currentqueueidx = 0;
+        backend::shufflequeue(app)?;
+    }
     Ok(())
 }
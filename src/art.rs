@@ -0,0 +1,319 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use ratatui::{layout::Rect, Frame};
+use ratatui_image::{picker::Picker, protocol::StatefulProtocol, StatefulImage};
+use crate::consts::{stablehash, Track};
+use crate::paths;
+
+static TIMEOUTSECS: u64 = 10;                   // give up on a thumbnail server after this long
+static MAXTHUMBNAILBYTES: u64 = 10 * 1024 * 1024; // thumbnails are small, anything bigger is not one
+
+/// state of the cover art shown in the now playing panel
+pub struct ArtState {
+    pub wanted: bool,                                       // set by the renderer when a panel can show art
+    picker: Picker,                                         // picks sixel/kitty/iterm2 or halfblocks
+    loadedkey: Option<String>,                              // cache key of the track whose art is loaded
+    protocol: Option<StatefulProtocol>,                     // the loaded image, encoded for the terminal
+    pending: Option<(String, Receiver<Option<PathBuf>>)>,   // thumbnail currently being fetched
+    failed: HashSet<String>,                                // tracks without art, so they are not refetched
+}
+
+impl fmt::Debug for ArtState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArtState")
+            .field("wanted", &self.wanted)
+            .field("protocol", &self.picker.protocol_type())
+            .field("loadedkey", &self.loadedkey)
+            .field("pending", &self.pending.as_ref().map(|(key, _)| key))
+            .finish()
+    }
+}
+
+impl ArtState {
+    pub fn new(picker: Picker) -> Self {
+        ArtState {
+            wanted: false,
+            picker,
+            loadedkey: None,
+            protocol: None,
+            pending: None,
+            failed: HashSet::new(),
+        }
+    }
+}
+
+/// picks the best graphics protocol the terminal supports
+/// 
+/// must be called after entering the alternate screen but before reading events
+/// 
+/// # returns
+/// * a picker for sixel, kitty or iterm2 graphics, or one that renders half blocks
+pub fn getpicker() -> Picker {
+    Picker::from_query_stdio().unwrap_or_else(|_| Picker::from_fontsize((8, 16)))
+}
+
+/// gets the key a track's thumbnail is cached under
+/// 
+/// # arguments
+/// * 'track' - the track
+/// 
+/// # returns
+/// * the cache key as a hex String, the same across builds so the cache stays valid
+pub fn cachekey(track: &Track) -> String {
+    format!("{:016x}", stablehash(track.id.as_bytes()))
+}
+
+/// gets the path a track's thumbnail is cached at
+pub fn thumbnailpath(key: &str) -> PathBuf {
+    paths::cachedir().join("thumbnails").join(format!("{}.png", key))
+}
+
+/// downloads a thumbnail from a known url into the cache, converting it to png
+/// 
+/// # arguments
+/// * 'url' - url of the image
+/// * 'dest' - where to write the png
+/// 
+/// # returns
+/// * none
+pub fn downloadthumbnail(url: &str, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    log::debug!("downloading thumbnail {}", url);
+    let response = ureq::get(url).timeout(Duration::from_secs(TIMEOUTSECS)).call()?;
+    let mut bytes = Vec::new();
+    response.into_reader().take(MAXTHUMBNAILBYTES).read_to_end(&mut bytes)?;
+    // youtube serves jpg and webp, decoding also makes sure it is an image at all
+    image::load_from_memory(&bytes)?.save_with_format(dest, image::ImageFormat::Png)?;
+    Ok(())
+}
+
+/// fetches a thumbnail into the cache
+/// 
+/// local files have their embedded cover extracted with ffmpeg,
+/// anything else is handed to yt-dlp
+/// 
+/// # arguments
+/// * 'url' - url or path of the track
+/// * 'dest' - where to write the png
+/// 
+/// # returns
+/// * none
+pub fn fetchthumbnail(url: &str, dest: &Path) -> Result<()> {
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let status = if Path::new(url).exists() {
+        Command::new("ffmpeg")
+            .args(["-y", "-v", "quiet", "-i", url, "-an", "-frames:v", "1"])
+            .arg(dest)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?
+    } else {
+        let template = dest.with_extension("%(ext)s");
+        Command::new("yt-dlp")
+            .args(["--skip-download", "--write-thumbnail", "--convert-thumbnails", "png", "--no-warnings"])
+            .arg("-o")
+            .arg(format!("thumbnail:{}", template.display()))
+            .arg(url)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?
+    };
+
+    log::debug!("thumbnail fetch for {} exited with {}", url, status);
+    if !status.success() || !dest.exists() {
+        return Err(anyhow::anyhow!("failed to fetch thumbnail for '{}'", url));
+    }
+
+    Ok(())
+}
+
+/// loads the art for the current track, fetching it in the background if it is not cached
+/// 
+/// # arguments
+/// * 'art' - mutable reference to the art state
+/// * 'track' - the currently playing track, if any
+/// 
+/// # returns
+/// * none
+pub fn update(art: &mut ArtState, track: Option<&Track>) {
+    if !art.wanted {
+        return;
+    }
+
+    // --- collect a finished fetch ---
+    if let Some((key, receiver)) = &art.pending {
+        match receiver.try_recv() {
+            Ok(Some(_)) => art.pending = None,
+            Ok(None) | Err(TryRecvError::Disconnected) => {
+                art.failed.insert(key.clone());
+                art.pending = None;
+            }
+            Err(TryRecvError::Empty) => {}
+        }
+    }
+
+    let Some(track) = track else {
+        art.loadedkey = None;
+        art.protocol = None;
+        return;
+    };
+
+    let key = cachekey(track);
+    if art.loadedkey.as_ref() == Some(&key) || art.failed.contains(&key) {
+        return;
+    }
+
+    let path = thumbnailpath(&key);
+    if path.exists() {
+        load(art, key, &path);
+    } else if art.pending.is_none() {
+        // --- fetch in the background so the ui does not block on yt-dlp ---
+        let (sender, receiver) = mpsc::channel();
+        let url = track.url.clone();
+        let thumbnail = track.thumbnail.clone();
+        thread::spawn(move || {
+            let result = fetch(&url, thumbnail.as_deref(), &path).ok().map(|_| path);
+            let _ = sender.send(result);
+        });
+        art.pending = Some((key, receiver));
+        art.protocol = None;
+    }
+}
+
+/// PRIVATE function that fetches a thumbnail, from the url the metadata found if there is one
+fn fetch(url: &str, thumbnail: Option<&str>, dest: &Path) -> Result<()> {
+    match thumbnail.filter(|t| t.starts_with("http")) {
+        Some(thumbnail) => downloadthumbnail(thumbnail, dest).or_else(|e| {
+            log::debug!("could not download thumbnail {}, asking yt-dlp: {}", thumbnail, e);
+            fetchthumbnail(url, dest)
+        }),
+        None => fetchthumbnail(url, dest),
+    }
+}
+
+/// PRIVATE function that loads a cached thumbnail, marking it failed if it cannot be decoded
+fn load(art: &mut ArtState, key: String, path: &Path) {
+    match image::ImageReader::open(path).and_then(|r| r.with_guessed_format()) {
+        Ok(reader) => match reader.decode() {
+            Ok(image) => art.protocol = Some(art.picker.new_resize_protocol(image)),
+            Err(_) => {
+                art.failed.insert(key.clone());
+                art.protocol = None;
+            }
+        },
+        Err(_) => {
+            art.failed.insert(key.clone());
+            art.protocol = None;
+        }
+    }
+    art.loadedkey = Some(key);
+}
+
+/// renders the art of the current track
+/// 
+/// # arguments
+/// * 'art' - mutable reference to the art state
+/// * 'frame' - mutable reference to the frame to render on
+/// * 'area' - the area to draw the art in
+/// 
+/// # returns
+/// * nothing
+pub fn render(art: &mut ArtState, frame: &mut Frame, area: Rect) {
+    if let Some(protocol) = &mut art.protocol {
+        frame.render_stateful_widget(StatefulImage::default(), area, protocol);
+    } else {
+        let placeholder = if art.pending.is_some() { "fetching art..." } else { "no art" };
+        frame.render_widget(
+            ratatui::widgets::Paragraph::new(placeholder).alignment(ratatui::layout::Alignment::Center),
+            area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, buffer::Buffer, style::Color, Terminal};
+    use super::*;
+    use crate::testing::{mockserver, mockserverbytes};
+
+    /// renders an image from tests/fixtures with half blocks, one pixel per half cell
+    fn renderfixture(name: &str, width: u16, height: u16) -> Buffer {
+        let mut art = ArtState::new(Picker::from_fontsize((1, 2)));
+        load(&mut art, String::from(name), Path::new(&format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)));
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| render(&mut art, frame, frame.area())).unwrap();
+        terminal.backend().buffer().clone()
+    }
+
+    #[test]
+    fn halfblocksuseonecellfortwopixels() {
+        // cover.png is 2x4: red green / blue blue / white black / white white
+        let buffer = renderfixture("cover.png", 2, 2);
+        let cell = |x: u16, y: u16| {
+            let cell = &buffer[(x, y)];
+            (cell.symbol().to_string(), cell.fg, cell.bg)
+        };
+        assert_eq!(cell(0, 0), (String::from("▀"), Color::Rgb(255, 0, 0), Color::Rgb(0, 0, 255)));
+        assert_eq!(cell(1, 0), (String::from("▀"), Color::Rgb(0, 255, 0), Color::Rgb(0, 0, 255)));
+        // the same colour twice is a space, and the brighter half is always drawn as the foreground
+        assert_eq!(cell(0, 1), (String::from(" "), Color::Rgb(255, 255, 255), Color::Rgb(255, 255, 255)));
+        assert_eq!(cell(1, 1), (String::from("▄"), Color::Rgb(255, 255, 255), Color::Rgb(0, 0, 0)));
+    }
+
+    #[test]
+    fn artthatcannotbedecodedisnotretried() {
+        let mut art = ArtState::new(Picker::from_fontsize((1, 2)));
+        let path = format!("{}/tests/fixtures/podcast.rss", env!("CARGO_MANIFEST_DIR"));
+        load(&mut art, String::from("broken"), Path::new(&path));
+        assert!(art.protocol.is_none());
+        assert!(art.failed.contains("broken"));
+
+        let mut terminal = Terminal::new(TestBackend::new(20, 1)).unwrap();
+        terminal.draw(|frame| render(&mut art, frame, frame.area())).unwrap();
+        assert_eq!(terminal.backend().buffer(), &Buffer::with_lines(["       no art       "]));
+    }
+
+    #[test]
+    fn cachekeysstaythesameacrossbuilds() {
+        let mut track = Track::new("title", "artist", 100, "https://example.com/a");
+        track.id = String::from("track-id");
+        assert_eq!(cachekey(&track), "5a019e98f89ecbae");
+    }
+
+    #[test]
+    fn aknownthumbnailisdownloadedwithoutytdlp() {
+        let cover = std::fs::read(format!("{}/tests/fixtures/cover.png", env!("CARGO_MANIFEST_DIR"))).unwrap();
+        let (server, handle) = mockserverbytes("200 OK", &cover);
+        let dest = std::env::temp_dir().join(format!("mpvlayer-test-thumbnail-{}.png", std::process::id()));
+
+        // the track url is not something yt-dlp could fetch, so only the download can succeed
+        fetch("not a url", Some(&format!("{}/vi/abc/hqdefault.jpg", server)), &dest).unwrap();
+        assert!(handle.join().unwrap().starts_with("GET /vi/abc/hqdefault.jpg"));
+        let mut art = ArtState::new(Picker::from_fontsize((1, 2)));
+        load(&mut art, String::from("downloaded"), &dest);
+        assert!(art.protocol.is_some());
+        let _ = std::fs::remove_file(&dest);
+    }
+
+    #[test]
+    fn athumbnailthatisnotanimageisanerror() {
+        let (server, handle) = mockserver("200 OK", "<html></html>");
+        let dest = std::env::temp_dir().join(format!("mpvlayer-test-notimage-{}.png", std::process::id()));
+        assert!(downloadthumbnail(&server, &dest).is_err());
+        handle.join().unwrap();
+        assert!(!dest.exists());
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::Result;
+use crate::config::{self, ReplayGain};
+use crate::consts::{App, StatusLevel};
+
+/// passes the audio settings to the player
+/// 
+/// the player keeps them for every track after, so this is only needed when they change
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn apply(app: &mut App) -> Result<()> {
+    app.player.setoption("replaygain", app.config.replaygain.mpvvalue())?;
+    app.player.setoption("af", &config::audiofilters(&app.config))?;
+    app.player.setoption("speed", &format!("{:.2}", app.config.speed))?;
+    Ok(())
+}
+
+/// PRIVATE function that saves the equalizer and speed to the config file
+/// 
+/// only those two are written, replaygain and loudnorm changes stay in this session
+fn persist(app: &mut App) {
+    let result = config::load().and_then(|mut saved| {
+        saved.eq = app.config.eq.clone();
+        saved.speed = app.config.speed;
+        config::save(&saved)
+    });
+    if let Err(e) = result {
+        app.status.push(StatusLevel::Error, format!("failed to save the config: {}", e));
+    }
+}
+
+/// switches to an equalizer preset
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'name' - name of the preset
+/// * 'save' - whether to write it to the config, false while previewing
+/// 
+/// # returns
+/// * none
+pub fn seteq(app: &mut App, name: &str, save: bool) -> Result<()> {
+    app.config.eq = String::from(name);
+    log::info!("eq: {}", name);
+    apply(app)?;
+    if save {
+        persist(app);
+    }
+    Ok(())
+}
+
+/// PRIVATE function that gets the speed after a step, 1.0 if the step is 0
+fn stepspeed(speed: f64, delta: f64) -> f64 {
+    let speed = if delta == 0.0 { 1.0 } else { speed + delta };
+    // round so repeated steps do not drift to 1.2000000002
+    (speed.clamp(config::MINSPEED, config::MAXSPEED) * 100.0).round() / 100.0
+}
+
+/// changes the playback speed, keeping the pitch
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'delta' - how much to change it by, 0 to reset it
+/// 
+/// # returns
+/// * none
+pub fn changespeed(app: &mut App, delta: f64) -> Result<()> {
+    app.config.speed = stepspeed(app.config.speed, delta);
+    log::info!("speed: {}", app.config.speed);
+    apply(app)?;
+    persist(app);
+    Ok(())
+}
+
+/// cycles replaygain between off, track and album for this session
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn cyclereplaygain(app: &mut App) -> Result<()> {
+    app.config.replaygain = match app.config.replaygain {
+        ReplayGain::Off => ReplayGain::Track,
+        ReplayGain::Track => ReplayGain::Album,
+        ReplayGain::Album => ReplayGain::Off
+    };
+    log::info!("replaygain: {:?}", app.config.replaygain);
+    app.status.push(StatusLevel::Info, format!("replaygain {}", describe(app)));
+    apply(app)
+}
+
+/// toggles loudness normalisation for this session
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn toggleloudnorm(app: &mut App) -> Result<()> {
+    app.config.loudnorm = !app.config.loudnorm;
+    log::info!("loudnorm: {}", app.config.loudnorm);
+    app.status.push(StatusLevel::Info, format!("loudness normalisation {}", if app.config.loudnorm { "on" } else { "off" }));
+    apply(app)
+}
+
+/// describes the equalizer and speed for the song info block, e.g. "eq vocal ─ 1.25x"
+/// 
+/// # arguments
+/// * 'app' - reference to the app state
+/// 
+/// # returns
+/// * the description as a String
+pub fn describeeq(app: &App) -> String {
+    format!("eq {} ─ {:.2}x", config::activepreset(&app.config).name, app.config.speed)
+}
+
+/// describes the gain settings for the ui, e.g. "track + loudnorm"
+/// 
+/// # arguments
+/// * 'app' - reference to the app state
+/// 
+/// # returns
+/// * the description as a String
+pub fn describe(app: &App) -> String {
+    let replaygain = match app.config.replaygain {
+        ReplayGain::Off => "off",
+        ReplayGain::Track => "track",
+        ReplayGain::Album => "album",
+    };
+    let mut parts = vec![String::from(replaygain)];
+    if app.config.loudnorm {
+        parts.push(String::from("loudnorm"));
+    }
+    parts.join(" + ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{entertrack, testapp};
+
+    /// gets an option the app passed to the player
+    fn option(app: &mut App, name: &str) -> String {
+        app.player.getproperty(name).unwrap().as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn applypassesthesettingstotheplayer() {
+        let mut app = testapp(1);
+        entertrack(&mut app, 0);
+        app.config.replaygain = ReplayGain::Album;
+        app.config.eq = String::from("treble boost");
+        app.config.speed = 1.5;
+        apply(&mut app).unwrap();
+        assert_eq!(option(&mut app, "replaygain"), "album");
+        assert_eq!(option(&mut app, "af"), config::audiofilters(&app.config));
+        assert_eq!(option(&mut app, "speed"), "1.50");
+    }
+
+    #[test]
+    fn replaygaincyclesthroughmpvsvalues() {
+        let mut app = testapp(1);
+        entertrack(&mut app, 0);
+        for expected in ["track", "album", "no", "track"] {
+            cyclereplaygain(&mut app).unwrap();
+            assert_eq!(option(&mut app, "replaygain"), expected);
+        }
+        assert_eq!(describe(&app), "track");
+    }
+
+    #[test]
+    fn loudnormisaddedtothefilters() {
+        let mut app = testapp(1);
+        entertrack(&mut app, 0);
+        toggleloudnorm(&mut app).unwrap();
+        assert_eq!(option(&mut app, "af"), "lavfi=[loudnorm=I=-16:TP=-1.5:LRA=11]");
+        assert_eq!(describe(&app), "off + loudnorm");
+        toggleloudnorm(&mut app).unwrap();
+        assert_eq!(option(&mut app, "af"), "");
+    }
+
+    #[test]
+    fn speedstepsareclampedandrounded() {
+        let mut speed = 1.0;
+        for _ in 0..4 {
+            speed = stepspeed(speed, 0.05);
+        }
+        assert_eq!(speed, 1.2);
+        assert_eq!(stepspeed(2.9, 0.25), config::MAXSPEED);
+        assert_eq!(stepspeed(0.3, -0.25), config::MINSPEED);
+        assert_eq!(stepspeed(2.5, 0.0), 1.0);
+    }
+}
@@ -1,8 +1,12 @@
-use std::process::{Command, Stdio};
+use std::collections::HashSet;
+use std::process::Command;
 use anyhow::Result;
 use rand::seq::SliceRandom;
-use serde_json::Value;
-use crate::consts::{App, RepeatType, Track, MAXQUEUELENGTH, MPVSOCKET};
+use crate::{bookmarks, crossfade, feeds, library, sleep, smart, supervisor};
+use crate::consts::{unixtime, App, RepeatType, ShuffleMode, StatusLevel, Track, MAXHISTORYLENGTH, MAXQUEUELENGTH};
+
+static SMARTRECENT: usize = 20;     // tracks at the end of the history that smart shuffle plays last
+static RESUMEMARGINSECS: u32 = 10;  // tracks left closer than this to their end start over
 
 /// gets list of video ids from playlist using yt-dlp
 /// 
@@ -11,6 +15,7 @@ use crate::consts::{App, RepeatType, Track, MAXQUEUELENGTH, MPVSOCKET};
 /// 
 /// # returns
 /// * 'ids' - list of video ids as a Vec<String>
+#[allow(dead_code)] // not used yet, kept for adding playlists from youtube (see the roadmap)
 pub fn getvidsfromplaylist(url: &str) -> Result<Vec<String>> {
     let output = Command::new("yt-dlp")
         .arg("--get-id")
@@ -35,8 +40,9 @@ pub fn getvidsfromplaylist(url: &str) -> Result<Vec<String>> {
 /// 
 /// # returns
 /// * 'url' - url of the playlist as a String
+#[allow(dead_code)] // not used yet, kept for adding playlists from youtube (see the roadmap)
 pub fn playlisturlfromid(id: String) -> String {
-    String::from(format!("https://www.youtube.com/playlist?list={}", id))
+    format!("https://www.youtube.com/playlist?list={}", id)
 }
 
 /// gets video url from id
@@ -46,269 +52,374 @@ pub fn playlisturlfromid(id: String) -> String {
 /// 
 /// # returns
 /// * 'url' - url of the video as a String
+#[allow(dead_code)] // not used yet, kept for adding playlists from youtube (see the roadmap)
 pub fn videourlfromid(id: String) -> String {
-    String::from(format!("https://www.youtube.com/watch?v={}", id))
+    format!("https://www.youtube.com/watch?v={}", id)
 }
 
-/// pauses playback
+/// plays the current track
 /// 
-/// # arguments 
-/// * none
+/// # arguments
+/// * 'app' - mutable reference to the app state
 /// 
 /// # returns
 /// * none
-pub fn pause(mpvsocket: &str) -> Result<()> {
-    let echo_output = Command::new("echo")
-        .arg(r#"{"command": ["cycle", "pause"]}"#)
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    if let Some(echoout) = echo_output.stdout {
-        let socatout = Command::new("socat")
-            .arg("-")
-            .arg(mpvsocket)
-            .stdin(Stdio::from(echoout)) 
-            .stdout(Stdio::null()) 
-            .stderr(Stdio::piped()) 
-            .output()?;
-
-        if !socatout.status.success() {
-            let stderr = String::from_utf8_lossy(&socatout.stderr);
-            eprintln!("failed to send pause command to mpv: {}", stderr);
-        }
-    } else {
-        eprintln!("failed to get stdout from echo command");
+pub fn playcurrenttrack(app: &mut App) -> Result<()> {
+    // --- safety checks ---
+    let trackidx = app.currentqueueidx as usize;
+
+    if app.queue.is_empty() || trackidx >= app.queue.len() {
+        // if there is nothing to play
+        log::info!("nothing to play at queue index {}, stopping", trackidx);
+        return stop(app);
     }
 
+    log::info!("playing queue[{}] '{}' ({})", trackidx, app.queue[trackidx].displaytitle(), app.queue[trackidx].url);
+
+    // --- reset progress timer and crash counter, a track skipped to mid crossfade cuts it short ---
+    crossfade::cut(app);
+    app.currentdurationsecs = 0;
+    supervisor::reset(&mut app.supervisor);
+
+    // --- replaces whatever was playing, long tracks carry on where they were left ---
+    let url = app.queue[trackidx].playurl().to_string();
+    let start = resumeposition(app, trackidx);
+    app.player.play(&url, start, false)?;
+    app.currentdurationsecs = start;
+    app.playing = true;
+    app.fadefrom = None;
+    app.fadevolume = None;
+    app.chaptersfetched = false;
+    app.streamtitle = None;
+    app.abloop = bookmarks::AbLoop::default();
+
+    countplay(app, trackidx);
+    preloadnext(app);
     Ok(())
 }
 
-/// Gets the current playback position from mpv in seconds
+/// PRIVATE function that does the bookkeeping for a track that just started
+/// 
+/// fills in the duration, counts the play and adds the track to the history
+fn countplay(app: &mut App, trackidx: usize) {
+    // --- only trust the player's duration if the resolver has not found one, streams have none ---
+    if app.queue[trackidx].duration == 0 && !app.queue[trackidx].islive() {
+        // mpv may not know it yet, the resolver will fill the duration in later if so
+        let duration = app.player.duration().unwrap_or(0);
+        if duration > 0 {
+            app.queue[trackidx].duration = duration;
+            let trackid = app.queue[trackidx].id.clone();
+            library::updatetrack(&mut app.playlists, &trackid, |t| {
+                if t.duration == 0 {
+                    t.duration = duration;
+                }
+            });
+        }
+    }
+
+    // --- count the play in the queue and in the library ---
+    let now = unixtime();
+    app.queue[trackidx].playcount += 1;
+    app.queue[trackidx].lastplayed = Some(now);
+    let trackid = app.queue[trackidx].id.clone();
+    library::updatetrack(&mut app.playlists, &trackid, |t| {
+        t.playcount += 1;
+        t.lastplayed = Some(now);
+    });
+    smart::refresh(app);
+
+    // --- remember the track for the history panel ---
+    app.history.push(app.queue[trackidx].clone());
+    if app.history.len() > MAXHISTORYLENGTH {
+        app.history.remove(0);
+    }
+}
+
+/// PRIVATE function that gets where a track in the queue should start, 0 for the beginning
+fn resumeposition(app: &App, trackidx: usize) -> u32 {
+    let Some(track) = app.queue.get(trackidx) else {
+        return 0;
+    };
+    // a track left in its last few seconds would end straight away
+    let nearend = track.duration > 0 && track.resumeat + RESUMEMARGINSECS >= track.duration;
+    if !track.resumable(app.config.resumeafter * 60) || nearend {
+        return 0;
+    }
+    track.resumeat
+}
+
+/// remembers how far into the current track playback is, called every second
+/// 
+/// only tracks that resume are remembered, see Track::resumable
 /// 
 /// # arguments
-/// * 'mpvsocket' - path to the mpv socket
+/// * 'app' - mutable reference to the app state
 /// 
 /// # returns
-/// * 'position' - current playback position in seconds as u32
-pub fn getplaybackpos(mpvsocket: &str) -> Result<u32> {
-    // Add timeout for socket operations
-    let timeout = std::time::Duration::from_secs(2);
-    
-    // unique request ID for this request
-    let requestid = rand::random::<u32>();
-    
-    let echoout = Command::new("echo")
-        .arg(format!(r#"{{"command":["get_property","time-pos"], "request_id": {}}}"#, requestid))
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    if let Some(echoout) = echoout.stdout {
-        // send the command to mpv via socket and capture the output
-        let socatout = Command::new("socat")
-            .arg("-T")
-            .arg(timeout.as_secs().to_string())
-            .arg("-")
-            .arg(mpvsocket)
-            .stdin(Stdio::from(echoout))
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()?;
-
-        if !socatout.status.success() {
-            let stderr = String::from_utf8_lossy(&socatout.stderr);
-            eprintln!("failed to get playback position from mpv: {}", stderr);
-            return Ok(0);
-        }
+/// * none
+pub fn rememberposition(app: &mut App) {
+    let position = app.currentdurationsecs;
+    let Some(track) = app.queue.get(app.currentqueueidx as usize) else {
+        return;
+    };
+    if !app.playing || !track.resumable(app.config.resumeafter * 60) || track.resumeat == position {
+        return;
+    }
+    let id = track.id.clone();
+    library::edittrack(app, &id, |t| t.resumeat = position);
+}
 
-        // parse the JSON response
-        let response = String::from_utf8_lossy(&socatout.stdout);
-        
-        // parse the response JSON
-        if let Ok(json) = serde_json::from_str::<Value>(&response) {
-            // extract the position value
-            if let Some(data) = json.get("data") {
-                if let Some(position) = data.as_f64() {
-                    return Ok(position.floor() as u32); // convert to u32 (seconds)
-                }
-            }
-        }
-        
-        // return 0 if we couldn't parse the position
-        return Ok(0);
+/// PRIVATE function that forgets the position of the current track once it has played to the end
+fn finishtrack(app: &mut App) {
+    let Some(track) = app.queue.get(app.currentqueueidx as usize) else {
+        return;
+    };
+    let (id, isepisode) = (track.id.clone(), track.isepisode());
+    if isepisode {
+        feeds::setplayed(app, &id, true);
     } else {
-        eprintln!("failed to get stdout from echo command");
-        return Ok(0); // return 0 on error
+        library::edittrack(app, &id, |t| t.resumeat = 0);
     }
 }
 
-pub fn gettotalduration(mpvsocket: &str) -> Result<u32> {
-    // unique request ID for this request
-    let requestid = rand::random::<u32>();
-    
-    let echoout = Command::new("echo")
-        .arg(format!(r#"{{"command":["get_property","duration"], "request_id": {}}}"#, requestid))
-        .stdout(Stdio::piped())
-        .spawn()?;
-
-    if let Some(echoout) = echoout.stdout {
-        // send the command to mpv via socket and capture the output
-        let socatout = Command::new("socat")
-            .arg("-")
-            .arg(mpvsocket)
-            .stdin(Stdio::from(echoout))
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output()?;
-
-        if !socatout.status.success() {
-            let stderr = String::from_utf8_lossy(&socatout.stderr);
-            eprintln!("failed to get total duration from mpv: {}", stderr);
-            return Ok(0);
-        }
+/// gets the queue index of the track that plays after the current one
+/// 
+/// the queue is already shuffled and repeated, so this only has to handle the end of it
+/// 
+/// # arguments
+/// * 'app' - reference to the app state
+/// 
+/// # returns
+/// * the index, or None at the end of the queue when repeat is off
+pub fn nextidx(app: &App) -> Option<u32> {
+    if app.queue.is_empty() {
+        return None;
+    }
 
-        // parse the JSON response
-        let response = String::from_utf8_lossy(&socatout.stdout);
-        
-        // parse the response JSON
-        if let Ok(json) = serde_json::from_str::<Value>(&response) {
-            // extract the position value
-            if let Some(data) = json.get("data") {
-                if let Some(position) = data.as_f64() {
-                    return Ok(position.floor() as u32); // convert to u32 (seconds)
-                }
-            }
+    if app.currentqueueidx as usize >= app.queue.len() - 1 {
+        if app.repeat == RepeatType::None {
+            None
+        } else {
+            Some(0)
         }
-        
-        // return 0 if we couldn't parse the position
-        return Ok(0);
     } else {
-        eprintln!("failed to get stdout from echo command");
-        return Ok(0); // return 0 on error
+        Some(app.currentqueueidx + 1)
     }
 }
 
-/// kills all currently running mpv processes using kill
-/// TODO: deprecate this
+/// tells the player which track comes next, so it can move on to it without a gap
+/// 
+/// called whenever the current track or the queue changes. if the player is not ready
+/// yet it is retried on the next tick
+/// 
 /// # arguments
-/// * none
+/// * 'app' - mutable reference to the app state
 /// 
 /// # returns
 /// * none
-pub fn killallmpv() {
-    let output = Command::new("pidof")
-        .arg("mpv")
-        .output()
-        .unwrap();
-
-    if !&output.status.success() {
+pub fn preloadnext(app: &mut App) {
+    app.preloadedidx = None;
+    app.preloadpending = false;
+    if !app.config.gapless || !app.player.isrunning() {
         return;
     }
 
-    for pid in output.stdout.split(|&x| x == b' ').into_iter().map(|x| std::str::from_utf8(x).unwrap()).into_iter() {
-        let output = Command::new("kill")
-            .arg(pid)
-            .output();
-
-        if !output.unwrap().status.success() {
-            eprintln!("failed to kill mpv with pid {}", pid);
+    // mpv would move on to a preloaded track when a stream drops, instead of letting it reconnect
+    let live = app.queue.get(app.currentqueueidx as usize).is_some_and(|t| t.islive());
+    // a track that resumes is started by playcurrenttrack instead, which can pass mpv where to start
+    let next = if app.stopaftercurrent || live { None } else { nextidx(app) }
+        .filter(|idx| resumeposition(app, *idx as usize) == 0);
+    let url = next.map(|idx| app.queue[idx as usize].playurl().to_string());
+    match app.player.preload(url.as_deref()) {
+        Ok(()) => {
+            log::debug!("preloaded queue[{:?}]", next);
+            app.preloadedidx = next;
+        }
+        Err(e) => {
+            log::debug!("could not preload yet: {}", e);
+            app.preloadpending = true;
         }
     }
 }
 
-/// plays the current track using mpv
-/// 
-/// spawns an mpv child process to play the track
+/// checks whether the player moved on to the preloaded track by itself
 /// 
 /// # arguments
 /// * 'app' - mutable reference to the app state
 /// 
 /// # returns
 /// * none
-pub fn playcurrenttrack(app: &mut App) -> Result<()> {
-    // --- safety checks ---
-    let trackidx = app.currentqueueidx as usize;
+pub fn checkadvance(app: &mut App) -> Result<()> {
+    let Some(nextidx) = app.preloadedidx else {
+        return Ok(());
+    };
+    if !app.player.advanced()? {
+        return Ok(());
+    }
 
-    if app.queue.is_empty() || trackidx >= app.queue.len() {
-        // if there is nothing to play
-        app.playing = false;
-        app.currentdurationsecs = 0;
+    log::info!("moved on to queue[{}] without a gap", nextidx);
+    finishtrack(app);
+    app.currentqueueidx = nextidx;
+    app.ui.queuestate.select(Some(nextidx as usize));
+    app.currentdurationsecs = 0;
+    app.chaptersfetched = false;
+    app.streamtitle = None;
+    bookmarks::clearabloop(app);
+    supervisor::reset(&mut app.supervisor);
 
-        // kill any existing child processes
-        if let Some(mut child) = app.mpv.take() {
-            let _ = child.kill().map_err(|e| eprintln!("failed to kill child: {}", e));
-        }
+    countplay(app, nextidx as usize);
+    preloadnext(app);
+    Ok(())
+}
+
+/// fades the volume between tracks and for the sleep timer
+/// 
+/// with crossfade set the current track fades in at its start, and out at its end when nothing
+/// crossfades into it (the end of the queue, stop after current). the old track of a crossfade
+/// is faded by crossfade::update. the volume is scaled from whatever it was when the fade
+/// started and put back afterwards
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn fade(app: &mut App) -> Result<()> {
+    if !app.playing {
         return Ok(());
     }
 
-    // --- kill previous child mpv instance ---
-    if let Some(mut child) = app.mpv.take() {
-        match child.kill() {
-            Ok(_) => { /* succesfully killed */ }
-            Err(e) => eprintln!("failed to kill child: {}", e),
+    let mut factor = sleep::volumefactor(app);
+    if app.config.crossfade > 0 {
+        let fadesecs = app.config.crossfade as f64;
+        let position = app.player.getproperty("time-pos")?.as_f64().unwrap_or(0.0);
+        let duration = app.queue.get(app.currentqueueidx as usize).map(|t| t.duration).unwrap_or(0) as f64;
+
+        let fadein = position / fadesecs;
+        let fadeout = if duration > 0.0 { (duration - position) / fadesecs } else { 1.0 };
+        factor = factor.min(fadein).min(fadeout);
+    }
+
+    // nothing to fade and nothing to undo
+    if factor >= 1.0 && app.fadevolume.is_none() {
+        return Ok(());
+    }
+    let from = match app.fadefrom {
+        Some(from) => from,
+        None => {
+            let from = app.player.getproperty("volume")?.as_f64().unwrap_or(100.0);
+            app.fadefrom = Some(from);
+            from
         }
-        // child.wait()?; // if issues occur
+    };
+    let percent = (100.0 * factor.clamp(0.0, 1.0)).round() as u32;
+
+    if app.fadevolume != Some(percent) {
+        app.player.setproperty("volume", serde_json::json!(from * percent as f64 / 100.0))?;
+        app.fadevolume = Some(percent);
+    }
+    // back to where it was, a volume changed from now on is the one the next fade starts from
+    if percent == 100 {
+        app.fadefrom = None;
+        app.fadevolume = None;
     }
+    Ok(())
+}
 
-    // --- get url ---
-    let trackurl = &app.queue[trackidx].url;
-    // let tracktitle = &app.queue[track_idx].title;
+/// seeks to a position in the current track
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'position' - seconds into the track
+/// 
+/// # returns
+/// * none
+pub fn seek(app: &mut App, position: u32) -> Result<()> {
+    app.player.command(serde_json::json!(["seek", position, "absolute"]))?;
+    app.currentdurationsecs = position;
+    Ok(())
+}
 
-    // --- reset progress timer ---
+/// stops playback and the player
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn stop(app: &mut App) -> Result<()> {
+    log::info!("stopping playback");
+    crossfade::cut(app);
+    app.player.stop();
+    app.playing = false;
     app.currentdurationsecs = 0;
-
-    // socket cleanup
-    let _ = std::fs::remove_file(MPVSOCKET);
-
-    // println!("attempting to play: '{}' from {}", tracktitle, trackurl); // debug print
-    let childproc = std::process::Command::new("mpv")
-        .arg("--no-video")
-        .arg("--no-terminal")
-        .arg(format!("--input-ipc-server={}", MPVSOCKET))
-        .arg("--pause") // start paused to give app time to get total duration
-        .arg("--keep-open=yes")
-        // .arg("--no-audio-display")? .arg("--vo=null")? // audio-only if needed 
-        // .arg("--really-quiet") // quieter output 
-        .arg(trackurl)
-        .stdout(std::process::Stdio::null())  // discard stdout
-        .stderr(std::process::Stdio::null())  // discard stderr
-        .spawn() // start the process
-        .map_err(|e| anyhow::anyhow!("failed to spawn mpv for url '{}': {}", trackurl, e))?;
-    
-    std::thread::sleep(std::time::Duration::from_millis(100));
-
-    app.queue[trackidx].duration = gettotalduration(MPVSOCKET)?;
-
-    app.mpv = Some(childproc);
+    app.preloadedidx = None;
+    app.preloadpending = false;
     Ok(())
 }
 
+/// moves on after the current track ends by itself
+/// 
+/// stops instead if stop after current is on, turning it off again
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn trackended(app: &mut App) -> Result<()> {
+    finishtrack(app);
+    if app.stopaftercurrent {
+        log::info!("stopping after the current track");
+        app.stopaftercurrent = false;
+        app.status.push(StatusLevel::Info, String::from("stopped after the current track"));
+        return stop(app);
+    }
+    playnexttrack(app)
+}
+
+/// toggles stopping after the current track
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn togglestopaftercurrent(app: &mut App) {
+    app.stopaftercurrent = !app.stopaftercurrent;
+    log::info!("stop after current: {}", app.stopaftercurrent);
+    // the player may already have the next track lined up
+    preloadnext(app);
+}
+
 /// plays the next track
 /// 
+/// stops at the end of the queue unless repeat is on
+/// 
 /// # arguments
 /// * 'app' - mutable reference to the app state
 /// 
 /// # returns
 /// * none
 pub fn playnexttrack(app: &mut App) -> Result<()> {
-    if app.queue.is_empty() || !app.currentqueueidx < app.queue.len() as u32 - 1 {
+    if app.queue.is_empty() {
         return Ok(());
     }
 
-    let nextidx = if app.currentqueueidx == app.queue.len() as u32 - 1 {
-        0
-    } else {
-        app.currentqueueidx + 1
+    let Some(nextidx) = nextidx(app) else {
+        log::info!("end of the queue");
+        return stop(app);
     };
 
     app.currentqueueidx = nextidx;
-    app.queuestate.select(Some(nextidx as usize));
-    app.playing = true;
-    playcurrenttrack(app)?;
-    Ok(())
+    app.ui.queuestate.select(Some(nextidx as usize));
+    playcurrenttrack(app)
 }
 
 /// plays the previous track
 /// 
+/// restarts the first track unless repeat is on
+/// 
 /// # arguments
 /// * 'app' - mutable reference to the app state
 /// 
@@ -319,19 +430,20 @@ pub fn playprevtrack(app: &mut App) -> Result<()> {
         return Ok(());
     }
     let previdx = if app.currentqueueidx == 0 {
-        app.queue.len() as u32 - 1   
+        if app.repeat == RepeatType::None {
+            0
+        } else {
+            app.queue.len() as u32 - 1
+        }
     } else {
         app.currentqueueidx - 1
     };
     app.currentqueueidx = previdx;
-    app.queuestate.select(Some(previdx as usize));
-    app.playing = true;
-    playcurrenttrack(app)?;
-
-    Ok(())
+    app.ui.queuestate.select(Some(previdx as usize));
+    playcurrenttrack(app)
 }
 
-/// pauses mpv if app.playing is true
+/// pauses or resumes playback, starting the current track again if it was stopped
 /// 
 /// # arguments
 /// * 'app' - mutable reference to the app state
@@ -339,22 +451,33 @@ pub fn playprevtrack(app: &mut App) -> Result<()> {
 /// # returns
 /// * none
 pub fn togglepause(app: &mut App) -> Result<()> {
-    if app.playing {
-        pause(MPVSOCKET)?;
-        app.playing = !app.playing;
+    if !app.player.isrunning() {
+        if !app.queue.is_empty() {
+            playcurrenttrack(app)?;
+        }
+        return Ok(());
     }
+
+    app.player.togglepause()?;
+    app.playing = !app.playing;
+    log::info!("playing: {}", app.playing);
     Ok(())
 }
 
-/// toggles shuffle
+/// cycles the shuffle mode
 /// 
 /// # arguments
 /// * 'app' - mutable reference to the app state
 /// 
 /// # returns
 /// * none
-pub fn toggleshuffle(app: &mut App) -> Result<()> {
-    app.shuffle = !app.shuffle;
+pub fn cycleshuffle(app: &mut App) -> Result<()> {
+    match app.shuffle {
+        ShuffleMode::Off => app.shuffle = ShuffleMode::Random,
+        ShuffleMode::Random => app.shuffle = ShuffleMode::Smart,
+        ShuffleMode::Smart => app.shuffle = ShuffleMode::Off
+    }
+    log::info!("shuffle: {:?}", app.shuffle);
     if !app.queue.is_empty() {
         shufflequeue(app)?;
     }
@@ -374,88 +497,432 @@ pub fn cyclerepeat(app: &mut App) -> Result<()> {
         RepeatType::All => app.repeat = RepeatType::One,
         RepeatType::One => app.repeat = RepeatType::None
     }
+    log::info!("repeat: {:?}", app.repeat);
     repeatqueue(app)?;
     Ok(())
 }
 
+/// PRIVATE function that finds where the current track is in a queue it was copied from
+/// 
+/// tries the same spot first so duplicates keep their place, then the first track with the same id
+fn findidx(queue: &[Track], idx: usize, current: Option<&Track>) -> usize {
+    let Some(current) = current else {
+        return 0;
+    };
+    if !queue.is_empty() && queue.get(idx % queue.len()) == Some(current) {
+        return idx % queue.len();
+    }
+    queue.iter().position(|t| t == current).unwrap_or(0)
+}
+
 /// repeats the queue
 /// 
+/// repeat all fills the queue with copies of itself and repeat one with copies of the
+/// current track. turning repeat off puts the queue from before back
+/// 
 /// # arguments
 /// * 'app' - mutable reference to the app state
 /// 
 /// # returns
 /// * none
 pub fn repeatqueue(app: &mut App) -> Result<()> {
-    if !app.queue.is_empty() {
-        match app.repeat {
-            RepeatType::All => {
-                // if queue is not at max length, repeat current queue until it reaches MAXQUEUELENGTH
-                if app.queue.len() < MAXQUEUELENGTH {
-                    let originallen = app.queue.len();
-                    while app.queue.len() < MAXQUEUELENGTH {
-                        let spacetofill = MAXQUEUELENGTH - app.queue.len();
-                        // chunks to add is the minimum of the remaining space and the original length
-                        let chunkstoadd = std::cmp::min(originallen, spacetofill);
-                        // append the extension to the queue
-                        let mut extension: Vec<Track> = app.queue[0..chunkstoadd].to_vec();
-                        app.queue.append(&mut extension);
-                    }
+    if app.queue.is_empty() {
+        return Ok(());
+    }
+
+    let current = app.queue.get(app.currentqueueidx as usize).cloned();
+    match app.repeat {
+        RepeatType::All | RepeatType::One => {
+            // start from the unrepeated queue, so cycling all -> one does not stack copies
+            let original = app.queuebeforerepeat.get_or_insert_with(|| app.queue.clone()).clone();
+
+            if app.repeat == RepeatType::All {
+                // repeat the original queue until it reaches MAXQUEUELENGTH
+                let mut repeated = original.clone();
+                while repeated.len() < MAXQUEUELENGTH {
+                    let spacetofill = MAXQUEUELENGTH - repeated.len();
+                    // chunks to add is the minimum of the remaining space and the original length
+                    let chunkstoadd = std::cmp::min(original.len(), spacetofill);
+                    repeated.extend_from_slice(&original[0..chunkstoadd]);
                 }
-            },
-            RepeatType::One => {
-                // get current song
-                let currentsong = app.queue[app.currentqueueidx as usize].clone();
+                app.currentqueueidx = findidx(&original, app.currentqueueidx as usize, current.as_ref()) as u32;
+                app.queue = repeated;
+            } else if let Some(currentsong) = current {
                 // repeat current song MAXQUEUELENGTH times
-                app.queue = vec![currentsong; MAXQUEUELENGTH as usize];
-            },
-            RepeatType::None => {
-                app.queuebeforerepeat = Some(app.queue.clone());
-                // for no repeat, we need to ensure the queue is in its original state
-                if let Some(ref original) = app.queuebeforerepeat {
-                    app.queue = original.clone();
-                } else {
-                    // if no original queue exists, use the current playlist
-                    let playlistidx = app.currentplaylistidx as usize;
-                    if playlistidx < app.playlists.len() {
-                        app.queue = app.playlists[playlistidx].tracks.clone();
-                    }
-                }
+                app.queue = vec![currentsong; MAXQUEUELENGTH];
+                app.currentqueueidx = 0;
+            }
+        },
+        RepeatType::None => {
+            // put the queue from before repeat was turned on back
+            if let Some(original) = app.queuebeforerepeat.take() {
+                app.currentqueueidx = findidx(&original, app.currentqueueidx as usize, current.as_ref()) as u32;
+                app.queue = original;
             }
         }
     }
+    app.ui.queuestate.select(Some(app.currentqueueidx as usize));
+    preloadnext(app);
 
     Ok(())
 }
 
 /// shuffles the queue
 /// 
+/// only the tracks after the current one are shuffled, so the current track keeps playing
+/// and what was already played stays behind it. turning shuffle off puts the queue from
+/// before back, with the current track where it was
+/// 
 /// # arguments
 /// * 'app' - mutable reference to the app state
 /// 
 /// # returns
 /// * none
 pub fn shufflequeue(app: &mut App) -> Result<()> {
-    if !app.queue.is_empty() {
-        if app.shuffle {
+    if app.queue.is_empty() {
+        return Ok(());
+    }
+
+    let currentidx = std::cmp::min(app.currentqueueidx as usize, app.queue.len() - 1);
+    if app.shuffle == ShuffleMode::Off {
+        let current = app.queue.get(currentidx).cloned();
+        let original = match app.queuebeforeshuffle.take() {
+            Some(originalqueue) => originalqueue,
+            None => app.playlists
+                .get(app.currentplaylistidx as usize)
+                .map(|p| p.tracks.clone())
+                .unwrap_or_else(|| app.queue.clone()),
+        };
+        app.currentqueueidx = findidx(&original, currentidx, current.as_ref()) as u32;
+        app.queue = original;
+    } else {
+        // keep the order from before shuffle was turned on, switching modes reshuffles
+        if app.queuebeforeshuffle.is_none() {
             app.queuebeforeshuffle = Some(app.queue.clone());
-            // Use the rng function from the rand crate
-            let mut rng = rand::rng();
-            app.queue.shuffle(&mut rng);
-            app.currentqueueidx = 0;
-            app.currentdurationsecs = 0;
-            app.queuestate.select(Some(0));
-        } else {
-            if let Some(originalqueue) = app.queuebeforeshuffle.clone() {
-                app.queue = originalqueue;
-            } else {
-                app.queue = app.playlists[app.currentplaylistidx as usize].clone().tracks;
-            }
-            app.queuebeforeshuffle = None;
-            app.currentqueueidx = 0;
-            app.currentdurationsecs = 0;
-            app.queuestate.select(Some(0));
         }
+
+        let mut remainder = app.queue.split_off(currentidx + 1);
+        // Use the rng function from the rand crate
+        let mut rng = rand::rng();
+        remainder.shuffle(&mut rng);
+        if app.shuffle == ShuffleMode::Smart {
+            remainder = smartorder(remainder, &app.history, app.queue.get(currentidx));
+        }
+        app.queue.append(&mut remainder);
+        app.currentqueueidx = currentidx as u32;
     }
+    app.ui.queuestate.select(Some(app.currentqueueidx as usize));
+    preloadnext(app);
 
     Ok(())
 }
+
+/// PRIVATE function that reorders shuffled tracks for smart shuffle
+/// 
+/// recently played tracks move to the end, then tracks are picked so the same artist
+/// does not play twice in a row where possible. otherwise the shuffled order is kept
+fn smartorder(mut tracks: Vec<Track>, history: &[Track], previous: Option<&Track>) -> Vec<Track> {
+    let recent: HashSet<&str> = history.iter().rev().take(SMARTRECENT).map(|t| t.id.as_str()).collect();
+    let isrecent = |t: &Track| recent.contains(t.id.as_str());
+
+    // stable, so the shuffled order is kept within both halves
+    tracks.sort_by_key(|t| isrecent(t));
+
+    let mut ordered = Vec::with_capacity(tracks.len());
+    let mut lastartist = previous.map(|t| t.artist.clone()).unwrap_or_default();
+    while !tracks.is_empty() {
+        // only look as far as the tracks that are as recent as the next one, recency comes first
+        let firstrecent = isrecent(&tracks[0]);
+        let pick = tracks
+            .iter()
+            .take_while(|t| isrecent(t) == firstrecent)
+            .position(|t| t.artist.is_empty() || t.artist != lastartist)
+            .unwrap_or(0);
+        let track = tracks.remove(pick);
+        lastartist = track.artist.clone();
+        ordered.push(track);
+    }
+
+    ordered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app;
+    use crate::consts::CurrentColumn;
+    use crate::testing::{entertrack, testapp};
+
+    fn currenttitle(app: &App) -> &str {
+        &app.queue[app.currentqueueidx as usize].title
+    }
+
+    #[test]
+    fn repeatnonestopsattheend() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 2);
+        playnexttrack(&mut app).unwrap();
+        assert!(!app.playing);
+        assert!(!app.player.isrunning());
+        assert_eq!(app.currentqueueidx, 2);
+    }
+
+    #[test]
+    fn repeatallwraps() {
+        let mut app = testapp(3);
+        app.repeat = RepeatType::All;
+        entertrack(&mut app, 2);
+        for _ in 0..(MAXQUEUELENGTH - 2) {
+            playnexttrack(&mut app).unwrap();
+        }
+        assert!(app.playing);
+        assert_eq!(app.currentqueueidx, 0);
+        assert_eq!(currenttitle(&app), "track 0");
+    }
+
+    #[test]
+    fn cyclingrepeatbacktononerestoresthequeue() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 1);
+        cyclerepeat(&mut app).unwrap(); // all
+        assert_eq!(app.queue.len(), MAXQUEUELENGTH);
+        cyclerepeat(&mut app).unwrap(); // one
+        assert!(app.queue.iter().all(|t| t.title == "track 1"));
+        cyclerepeat(&mut app).unwrap(); // none
+        assert_eq!(app.queue.len(), 3);
+        assert_eq!(currenttitle(&app), "track 1");
+    }
+
+    #[test]
+    fn enterplaystheselectedtrack() {
+        let mut app = testapp(5);
+        entertrack(&mut app, 3);
+        assert_eq!(currenttitle(&app), "track 3");
+        assert_eq!(app.history.last().map(|t| t.title.as_str()), Some("track 3"));
+        assert_eq!(app.ui.queuestate.selected(), Some(3));
+    }
+
+    #[test]
+    fn enterwithshufflekeepstheselectedtrackcurrent() {
+        let mut app = testapp(5);
+        app.shuffle = ShuffleMode::Random;
+        entertrack(&mut app, 3);
+        assert_eq!(currenttitle(&app), "track 3");
+        assert_eq!(app.history.last().map(|t| t.title.as_str()), Some("track 3"));
+    }
+
+    #[test]
+    fn enterinthequeuekeepsthequeue() {
+        let mut app = testapp(5);
+        app.shuffle = ShuffleMode::Random;
+        entertrack(&mut app, 0);
+        let queue = app.queue.clone();
+
+        app.ui.currentcolumn = CurrentColumn::Queue;
+        app.ui.queuestate.select(Some(2));
+        app::handleenter(&mut app).unwrap();
+        assert_eq!(app.queue, queue);
+        assert_eq!(app.currentqueueidx, 2);
+        assert_eq!(app.history.last(), queue.get(2));
+    }
+
+    #[test]
+    fn shuffleonlyshufflestheremainder() {
+        let mut app = testapp(10);
+        entertrack(&mut app, 4);
+        let played = app.queue[..=4].to_vec();
+        cycleshuffle(&mut app).unwrap(); // random
+        assert_eq!(app.currentqueueidx, 4);
+        assert_eq!(app.queue[..=4], played[..]);
+        assert_eq!(app.queue.len(), 10);
+    }
+
+    #[test]
+    fn shuffleoffreturnstotheoriginalposition() {
+        let mut app = testapp(10);
+        entertrack(&mut app, 0);
+        cycleshuffle(&mut app).unwrap(); // random
+        for _ in 0..6 {
+            playnexttrack(&mut app).unwrap();
+        }
+        let title = currenttitle(&app).to_string();
+        cycleshuffle(&mut app).unwrap(); // smart
+        assert_eq!(currenttitle(&app), title);
+        cycleshuffle(&mut app).unwrap(); // off
+        assert_eq!(currenttitle(&app), title);
+        assert_eq!(app.queue, app.playlists[0].tracks);
+        assert_eq!(app.currentqueueidx as usize, app.queue.iter().position(|t| t.title == title).unwrap());
+    }
+
+    #[test]
+    fn smartshufflespreadsartists() {
+        let tracks: Vec<Track> = (0..8)
+            .map(|i| Track::new(&format!("track {}", i), if i < 4 { "a" } else { "b" }, 100, &format!("https://example.com/{}", i)))
+            .collect();
+        let ordered = smartorder(tracks, &[], None);
+        assert_eq!(ordered.len(), 8);
+        assert!(ordered.windows(2).all(|pair| pair[0].artist != pair[1].artist));
+    }
+
+    #[test]
+    fn smartshuffleplaysrecenttrackslast() {
+        let mut app = testapp(10);
+        for idx in [7, 8, 9] {
+            entertrack(&mut app, idx);
+        }
+        entertrack(&mut app, 0);
+        app.shuffle = ShuffleMode::Smart;
+        shufflequeue(&mut app).unwrap();
+        let last: HashSet<&str> = app.queue[7..].iter().map(|t| t.title.as_str()).collect();
+        assert_eq!(last, HashSet::from(["track 7", "track 8", "track 9"]));
+    }
+
+    #[test]
+    fn togglepauseresumes() {
+        let mut app = testapp(2);
+        entertrack(&mut app, 0);
+        togglepause(&mut app).unwrap();
+        assert!(!app.playing);
+        togglepause(&mut app).unwrap();
+        assert!(app.playing);
+    }
+
+    #[test]
+    fn togglepauseafterstopplaysagain() {
+        let mut app = testapp(2);
+        entertrack(&mut app, 1);
+        stop(&mut app).unwrap();
+        togglepause(&mut app).unwrap();
+        assert!(app.playing);
+        assert!(app.player.isrunning());
+        assert_eq!(currenttitle(&app), "track 1");
+    }
+
+    #[test]
+    fn prevonthefirsttrackrestartsit() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 0);
+        playprevtrack(&mut app).unwrap();
+        assert_eq!(app.currentqueueidx, 0);
+        assert_eq!(app.history.len(), 2);
+    }
+
+    #[test]
+    fn gaplesstransitionmovesthequeuealong() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 0);
+        assert_eq!(app.preloadedidx, Some(1));
+
+        // the player moved on to the preloaded track by itself
+        app.player.command(serde_json::json!(["set_property", "playlist-pos", 1])).unwrap();
+        checkadvance(&mut app).unwrap();
+        assert_eq!(currenttitle(&app), "track 1");
+        assert_eq!(app.history.last().map(|t| t.title.as_str()), Some("track 1"));
+        assert_eq!(app.preloadedidx, Some(2));
+    }
+
+    #[test]
+    fn nothingispreloadedattheendwithoutrepeat() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 2);
+        assert_eq!(app.preloadedidx, None);
+        cyclerepeat(&mut app).unwrap(); // all
+        assert_eq!(app.preloadedidx.map(|idx| app.queue[idx as usize].title.as_str()), Some("track 0"));
+    }
+
+    #[test]
+    fn preloadfollowsshuffle() {
+        let mut app = testapp(10);
+        entertrack(&mut app, 0);
+        cycleshuffle(&mut app).unwrap(); // random
+        assert_eq!(app.preloadedidx, Some(1));
+        let next = app.queue[1].title.clone();
+        app.player.command(serde_json::json!(["set_property", "playlist-pos", 1])).unwrap();
+        checkadvance(&mut app).unwrap();
+        assert_eq!(currenttitle(&app), next);
+    }
+
+    #[test]
+    fn stopaftercurrentstopsonce() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 0);
+        togglestopaftercurrent(&mut app);
+        assert_eq!(app.preloadedidx, None);
+
+        trackended(&mut app).unwrap();
+        assert!(!app.playing);
+        assert!(!app.stopaftercurrent);
+        assert_eq!(currenttitle(&app), "track 0");
+
+        // a track that was picked by hand still moves on
+        togglepause(&mut app).unwrap();
+        trackended(&mut app).unwrap();
+        assert_eq!(currenttitle(&app), "track 1");
+    }
+
+    #[test]
+    fn episodesresumeandaremarkedplayed() {
+        let mut app = testapp(2);
+        for track in app.playlists[0].tracks.iter_mut() {
+            track.source = crate::consts::SourceKind::Episode;
+        }
+        app.playlists[0].tracks[0].resumeat = 42;
+        entertrack(&mut app, 0);
+        assert_eq!(app.player.position().unwrap(), 42);
+
+        app.currentdurationsecs = 60;
+        rememberposition(&mut app);
+        assert_eq!(app.playlists[0].tracks[0].resumeat, 60);
+
+        trackended(&mut app).unwrap();
+        assert!(app.playlists[0].tracks[0].played);
+        assert_eq!(app.playlists[0].tracks[0].resumeat, 0);
+    }
+
+    #[test]
+    fn longtracksresumeandstartoverwhenfinished() {
+        let mut app = testapp(3);
+        app.playlists[0].tracks[1].duration = 2 * 60 * 60;
+        entertrack(&mut app, 1);
+        app.currentdurationsecs = 1800;
+        rememberposition(&mut app);
+        assert_eq!(app.playlists[0].tracks[1].resumeat, 1800);
+        playnexttrack(&mut app).unwrap();
+
+        // short tracks are not remembered
+        app.currentdurationsecs = 50;
+        rememberposition(&mut app);
+        assert_eq!(app.playlists[0].tracks[2].resumeat, 0);
+
+        playprevtrack(&mut app).unwrap();
+        assert_eq!(app.player.position().unwrap(), 1800);
+        trackended(&mut app).unwrap();
+        assert_eq!(app.playlists[0].tracks[1].resumeat, 0);
+    }
+
+    #[test]
+    fn fadingscalestheusersvolumeandputsitback() {
+        let mut app = testapp(2);
+        app.config.crossfade = 10;
+        entertrack(&mut app, 0);
+        app.player.setproperty("volume", serde_json::json!(60)).unwrap();
+        let volume = |app: &mut App| app.player.getproperty("volume").unwrap().as_f64().unwrap();
+
+        fade(&mut app).unwrap();
+        assert_eq!(volume(&mut app), 0.0);
+        app.player.setproperty("time-pos", serde_json::json!(5)).unwrap();
+        fade(&mut app).unwrap();
+        assert_eq!(volume(&mut app), 30.0);
+        app.player.setproperty("time-pos", serde_json::json!(50)).unwrap();
+        fade(&mut app).unwrap();
+        assert_eq!(volume(&mut app), 60.0);
+        assert_eq!(app.fadefrom, None);
+
+        // a volume changed after the fade is the one the next fade starts from
+        app.player.setproperty("volume", serde_json::json!(80)).unwrap();
+        app.player.setproperty("time-pos", serde_json::json!(95)).unwrap();
+        fade(&mut app).unwrap();
+        assert_eq!(volume(&mut app), 40.0);
+    }
+
+}
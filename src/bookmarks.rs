@@ -0,0 +1,179 @@
+use anyhow::Result;
+use serde_json::json;
+use crate::backend;
+use crate::consts::{App, Bookmark, StatusLevel};
+use crate::library;
+
+/// a part of the current track played over and over, using mpv's ab-loop-a and ab-loop-b
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub struct AbLoop {
+    pub a: Option<u32>,     // seconds, where the loop starts
+    pub b: Option<u32>,     // seconds, where it jumps back to a, the loop is only on once this is set
+}
+
+/// sets the loop points in mpv, also used to put them back after mpv restarts
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn applyabloop(app: &mut App) -> Result<()> {
+    let point = |p: Option<u32>| p.map(|secs| json!(secs)).unwrap_or(json!("no"));
+    app.player.setproperty("ab-loop-a", point(app.abloop.a))?;
+    app.player.setproperty("ab-loop-b", point(app.abloop.b))?;
+    Ok(())
+}
+
+/// sets point a, then point b, then turns the loop off, like mpv's l key
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn cycleabloop(app: &mut App) -> Result<()> {
+    let position = app.currentdurationsecs;
+    match (app.abloop.a, app.abloop.b) {
+        (None, _) => {
+            app.abloop.a = Some(position);
+            app.status.push(StatusLevel::Info, format!("loop from {}, press again to set the end", prettytime(position)));
+        }
+        (Some(a), None) => {
+            if position <= a {
+                return Err(anyhow::anyhow!("the end of the loop has to be after {}", prettytime(a)));
+            }
+            app.abloop.b = Some(position);
+            app.status.push(StatusLevel::Info, format!("looping {}-{}", prettytime(a), prettytime(position)));
+        }
+        (Some(_), Some(_)) => {
+            app.abloop = AbLoop::default();
+            app.status.push(StatusLevel::Info, String::from("loop off"));
+        }
+    }
+    applyabloop(app)
+}
+
+/// forgets the loop, used when another track starts
+/// 
+/// mpv keeps the loop points across files, so they are cleared there too
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn clearabloop(app: &mut App) {
+    if app.abloop == AbLoop::default() {
+        return;
+    }
+    app.abloop = AbLoop::default();
+    if app.player.isrunning() {
+        if let Err(e) = applyabloop(app) {
+            log::debug!("could not clear the loop: {}", e);
+        }
+    }
+}
+
+/// PRIVATE function that formats seconds as m:ss
+fn prettytime(secs: u32) -> String {
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// PRIVATE function that changes the bookmarks of the current track in the queue and the library
+fn editbookmarks(app: &mut App, f: impl Fn(&mut Vec<Bookmark>)) {
+    let Some(track) = app.queue.get_mut(app.currentqueueidx as usize) else {
+        return;
+    };
+    f(&mut track.bookmarks);
+    let trackid = track.id.clone();
+    library::updatetrack(&mut app.playlists, &trackid, |t| f(&mut t.bookmarks));
+}
+
+/// bookmarks a position in the current track
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'name' - name of the bookmark
+/// * 'position' - seconds into the track
+/// 
+/// # returns
+/// * none
+pub fn addbookmark(app: &mut App, name: &str, position: u32) {
+    let bookmark = Bookmark { name: String::from(name), position };
+    editbookmarks(app, |bookmarks| {
+        bookmarks.push(bookmark.clone());
+        bookmarks.sort_by_key(|b| b.position);
+    });
+    app.status.push(StatusLevel::Info, format!("bookmarked {} at {}", name, prettytime(position)));
+}
+
+/// removes a bookmark from the current track
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'idx' - index into the current track's bookmarks
+/// 
+/// # returns
+/// * none
+pub fn removebookmark(app: &mut App, idx: usize) {
+    let Some(bookmark) = app.queue.get(app.currentqueueidx as usize).and_then(|t| t.bookmarks.get(idx)).cloned() else {
+        return;
+    };
+    editbookmarks(app, |bookmarks| bookmarks.retain(|b| *b != bookmark));
+}
+
+/// jumps to a bookmark in the current track
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'idx' - index into the current track's bookmarks
+/// 
+/// # returns
+/// * none
+pub fn jumptobookmark(app: &mut App, idx: usize) -> Result<()> {
+    let Some(position) = app.queue.get(app.currentqueueidx as usize).and_then(|t| t.bookmarks.get(idx)).map(|b| b.position) else {
+        return Ok(());
+    };
+    backend::seek(app, position)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::testing::{entertrack, testapp};
+
+    #[test]
+    fn abloopcyclesandclearsonthenexttrack() {
+        let mut app = testapp(2);
+        entertrack(&mut app, 0);
+        app.currentdurationsecs = 10;
+        cycleabloop(&mut app).unwrap();
+        app.currentdurationsecs = 5;
+        assert!(cycleabloop(&mut app).is_err());
+        app.currentdurationsecs = 20;
+        cycleabloop(&mut app).unwrap();
+        assert_eq!(app.player.getproperty("ab-loop-a").unwrap(), serde_json::json!(10));
+        assert_eq!(app.player.getproperty("ab-loop-b").unwrap(), serde_json::json!(20));
+
+        backend::playnexttrack(&mut app).unwrap();
+        assert_eq!(app.abloop, AbLoop::default());
+    }
+
+    #[test]
+    fn bookmarksaresavedtothelibrary() {
+        let mut app = testapp(1);
+        entertrack(&mut app, 0);
+        addbookmark(&mut app, "chorus", 50);
+        addbookmark(&mut app, "intro", 5);
+        let names: Vec<&str> = app.playlists[0].tracks[0].bookmarks.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, ["intro", "chorus"]);
+
+        jumptobookmark(&mut app, 1).unwrap();
+        assert_eq!(app.currentdurationsecs, 50);
+        removebookmark(&mut app, 0);
+        assert_eq!(app.queue[0].bookmarks.len(), 1);
+        assert_eq!(app.playlists[0].tracks[0].bookmarks.len(), 1);
+    }
+}
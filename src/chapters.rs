@@ -0,0 +1,160 @@
+use anyhow::Result;
+use serde_json::Value;
+use crate::consts::{App, Chapter};
+use crate::{backend, library};
+
+static RESTARTSECS: u32 = 3;    // previous chapter restarts the current one when this far into it
+
+/// reads a chapter list from yt-dlp, ffprobe or mpv
+/// 
+/// they all use a list of objects but name the fields differently, yt-dlp uses
+/// start_time and title, ffprobe start_time as a string and tags.title, mpv time and title
+/// 
+/// # arguments
+/// * 'list' - the chapter list
+/// 
+/// # returns
+/// * the chapters sorted by start, empty if there are none
+pub fn parsechapters(list: &Value) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = list
+        .as_array()
+        .map(|items| items.iter().enumerate().filter_map(|(idx, item)| {
+            let start = ["start_time", "time"].iter().find_map(|key| {
+                let value = item.get(key)?;
+                value.as_f64().or_else(|| value.as_str()?.parse().ok())
+            })?;
+            let title = item.get("title")
+                .or_else(|| item.get("tags")?.get("title"))
+                .and_then(|t| t.as_str())
+                .filter(|t| !t.is_empty())
+                .map(String::from)
+                .unwrap_or_else(|| format!("chapter {}", idx + 1));
+            Some(Chapter { title, start: start.max(0.0).floor() as u32 })
+        }).collect())
+        .unwrap_or_default();
+
+    chapters.sort_by_key(|c| c.start);
+    chapters
+}
+
+/// gets the chapter playing at a position
+/// 
+/// # arguments
+/// * 'chapters' - the chapters, sorted by start
+/// * 'position' - position in seconds
+/// 
+/// # returns
+/// * index of the chapter, None before the first one or if there are none
+pub fn currentchapter(chapters: &[Chapter], position: u32) -> Option<usize> {
+    chapters.iter().rposition(|c| c.start <= position)
+}
+
+/// asks the player for the chapters of the current track if the resolver did not find any
+/// 
+/// tried once a second until the player has loaded the file, then not again for this play
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn update(app: &mut App) -> Result<()> {
+    let trackidx = app.currentqueueidx as usize;
+    if app.chaptersfetched || !app.playing || trackidx >= app.queue.len() {
+        return Ok(());
+    }
+    if !app.queue[trackidx].chapters.is_empty() {
+        app.chaptersfetched = true;
+        return Ok(());
+    }
+
+    let chapters = parsechapters(&app.player.getproperty("chapter-list")?);
+    // an empty list before the file has started may just mean it is still loading
+    if chapters.is_empty() && app.currentdurationsecs == 0 {
+        return Ok(());
+    }
+    app.chaptersfetched = true;
+    if chapters.is_empty() {
+        return Ok(());
+    }
+
+    log::info!("found {} chapters in '{}'", chapters.len(), app.queue[trackidx].displaytitle());
+    app.queue[trackidx].chapters = chapters.clone();
+    let trackid = app.queue[trackidx].id.clone();
+    library::updatetrack(&mut app.playlists, &trackid, |t| {
+        if t.chapters.is_empty() {
+            t.chapters = chapters.clone();
+        }
+    });
+    Ok(())
+}
+
+/// jumps to the start of the next chapter
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn nextchapter(app: &mut App) -> Result<()> {
+    let Some(track) = app.queue.get(app.currentqueueidx as usize) else {
+        return Ok(());
+    };
+    let position = app.currentdurationsecs;
+    if let Some(start) = track.chapters.iter().map(|c| c.start).find(|start| *start > position) {
+        backend::seek(app, start)?;
+    }
+    Ok(())
+}
+
+/// jumps to the start of the current chapter, or the previous one if the current one just started
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn prevchapter(app: &mut App) -> Result<()> {
+    let Some(track) = app.queue.get(app.currentqueueidx as usize) else {
+        return Ok(());
+    };
+    let position = app.currentdurationsecs;
+    let Some(current) = currentchapter(&track.chapters, position) else {
+        return Ok(());
+    };
+
+    let target = if position - track.chapters[current].start < RESTARTSECS && current > 0 {
+        track.chapters[current - 1].start
+    } else {
+        track.chapters[current].start
+    };
+    backend::seek(app, target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{entertrack, testapp};
+
+    #[test]
+    fn chapterjumps() {
+        let mut app = testapp(1);
+        entertrack(&mut app, 0);
+        app.queue[0].chapters = parsechapters(&serde_json::json!([
+            {"title": "intro", "start_time": 0.0},
+            {"title": "second", "time": 60.5},
+            {"tags": {"title": "third"}, "start_time": "120.000000"},
+        ]));
+        assert_eq!(app.queue[0].chapters[2].title, "third");
+
+        app.currentdurationsecs = 30;
+        nextchapter(&mut app).unwrap();
+        assert_eq!(app.currentdurationsecs, 60);
+        // right after a chapter starts previous goes back one, later it restarts the chapter
+        prevchapter(&mut app).unwrap();
+        assert_eq!(app.currentdurationsecs, 0);
+        app.currentdurationsecs = 130;
+        prevchapter(&mut app).unwrap();
+        assert_eq!(app.currentdurationsecs, 120);
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs;
+use std::path::PathBuf;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::paths;
+
+/// which replaygain tags mpv should use
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayGain {
+    #[default]
+    Off,
+    Track,      // even out every track
+    Album       // even out albums, keeping the differences between their tracks
+}
+
+impl ReplayGain {
+    /// gets the value mpv's --replaygain expects
+    pub fn mpvvalue(&self) -> &'static str {
+        match self {
+            ReplayGain::Off => "no",
+            ReplayGain::Track => "track",
+            ReplayGain::Album => "album",
+        }
+    }
+}
+
+pub static EQBANDS: [u32; 10] = [31, 62, 125, 250, 500, 1000, 2000, 4000, 8000, 16000]; // centre of each band in Hz
+pub static MINSPEED: f64 = 0.25;
+pub static MAXSPEED: f64 = 3.0;
+
+/// a named equalizer setting
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqPreset {
+    pub name: String,
+    pub gains: Vec<f64>     // gain of each band in EQBANDS in dB, missing bands are 0
+}
+
+impl EqPreset {
+    fn new(name: &str, gains: [f64; 10]) -> Self {
+        EqPreset { name: String::from(name), gains: gains.to_vec() }
+    }
+}
+
+/// settings read from $XDG_CONFIG_HOME/mpvlayer/config.json
+///
+/// every field has a default so a config written by an older version, or one with only
+/// a few fields in it, still loads
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub gapless: bool,      // preload the next track in mpv so there is no gap between tracks
+    pub crossfade: u32,     // seconds the end of a track and the start of the next overlap for, 0 for none
+    pub replaygain: ReplayGain, // replaygain tags to apply, only files with tags are affected
+    pub loudnorm: bool,     // normalise loudness with ffmpeg's loudnorm, for everything without tags
+    pub eq: String,         // name of the active equalizer preset
+    pub eqpresets: Vec<EqPreset>, // user defined presets, shown after the built in ones
+    pub speed: f64,         // playback speed, mpv keeps the pitch the same
+    pub sleeppresets: Vec<u32>, // sleep timer lengths in minutes the key cycles through
+    pub sleepfade: u32,     // seconds to fade out for before the sleep timer pauses
+    pub sleepquits: bool,   // quit instead of pausing when the sleep timer goes off
+    pub sponsorblock: bool, // skip segments of youtube videos, this sends their ids to sponsorblockapi
+    pub sponsorblockapi: String, // sponsorblock compatible server to get segments from
+    pub sponsorblockcategories: Vec<String>, // segment categories to skip
+    pub feedrefresh: u32,   // minutes between checking podcast feeds for new episodes
+    pub resumeafter: u32,   // minutes a track has to be to carry on where it was left, 0 for never
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            gapless: true,
+            crossfade: 0,
+            replaygain: ReplayGain::Off,
+            loudnorm: false,
+            eq: String::from("flat"),
+            eqpresets: Vec::new(),
+            speed: 1.0,
+            sleeppresets: vec![15, 30, 45, 60, 90],
+            sleepfade: 30,
+            sleepquits: false,
+            sponsorblock: false,
+            sponsorblockapi: String::from("https://sponsor.ajay.app"),
+            sponsorblockcategories: ["sponsor", "selfpromo", "interaction", "music_offtopic"].map(String::from).to_vec(),
+            feedrefresh: 60,
+            resumeafter: 20,
+        }
+    }
+}
+
+/// gets the path the config is read from
+pub fn configpath() -> PathBuf {
+    paths::configdir().join("config.json")
+}
+
+/// loads the config, writing the defaults out on first run so there is a file to edit
+/// 
+/// # arguments
+/// * none
+/// 
+/// # returns
+/// * the config
+pub fn load() -> Result<Config> {
+    let path = configpath();
+    if !path.exists() {
+        let config = Config::default();
+        save(&config)?;
+        return Ok(config);
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    let config = serde_json::from_str(&contents)
+        .map_err(|e| anyhow::anyhow!("failed to parse config '{}': {}", path.display(), e))?;
+
+    Ok(config)
+}
+
+/// saves the config
+/// 
+/// # arguments
+/// * 'config' - the config to save
+/// 
+/// # returns
+/// * none
+pub fn save(config: &Config) -> Result<()> {
+    let path = configpath();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temppath = path.with_extension("json.tmp");
+    fs::write(&temppath, serde_json::to_string_pretty(config)?)?;
+    fs::rename(&temppath, &path)?;
+
+    Ok(())
+}
+
+/// gets the extra mpv flags the config asks for
+/// 
+/// # arguments
+/// * 'config' - the config
+/// 
+/// # returns
+/// * the flags as a Vec<String>
+pub fn mpvargs(config: &Config) -> Vec<String> {
+    let mut args = Vec::new();
+    if config.gapless {
+        args.push(String::from("--gapless-audio=yes"));
+        args.push(String::from("--prefetch-playlist=yes"));
+    }
+    args
+}
+
+/// gets mpv's audio filter chain for the config
+/// 
+/// # arguments
+/// * 'config' - the config
+/// 
+/// # returns
+/// * the filters for mpv's af option, empty for none
+pub fn audiofilters(config: &Config) -> String {
+    let mut filters = Vec::new();
+
+    // one peaking filter per band that is not flat
+    let bands: Vec<String> = activepreset(config)
+        .gains
+        .iter()
+        .zip(EQBANDS.iter())
+        .filter(|(gain, _)| **gain != 0.0)
+        .map(|(gain, freq)| format!("equalizer=f={}:t=o:w=1:g={}", freq, gain))
+        .collect();
+    if !bands.is_empty() {
+        filters.push(format!("lavfi=[{}]", bands.join(",")));
+    }
+
+    if config.loudnorm {
+        // EBU R128 at the usual streaming target
+        filters.push(String::from("lavfi=[loudnorm=I=-16:TP=-1.5:LRA=11]"));
+    }
+    filters.join(",")
+}
+
+/// gets every equalizer preset, the built in ones first
+/// 
+/// # arguments
+/// * 'config' - the config holding the user's presets
+/// 
+/// # returns
+/// * the presets as a Vec<EqPreset>
+pub fn eqpresets(config: &Config) -> Vec<EqPreset> {
+    let mut presets = vec![
+        EqPreset::new("flat", [0.0; 10]),
+        EqPreset::new("bass boost", [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+        EqPreset::new("vocal", [-2.0, -2.0, -1.0, 0.0, 2.0, 4.0, 4.0, 3.0, 1.0, 0.0]),
+        EqPreset::new("treble boost", [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 2.0, 4.0, 5.0, 6.0]),
+    ];
+    presets.extend(config.eqpresets.iter().cloned());
+    presets
+}
+
+/// gets the active equalizer preset, flat if the config names one that does not exist
+/// 
+/// # arguments
+/// * 'config' - the config
+/// 
+/// # returns
+/// * the preset
+pub fn activepreset(config: &Config) -> EqPreset {
+    let presets = eqpresets(config);
+    presets.iter().find(|p| p.name == config.eq).unwrap_or(&presets[0]).clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// gets a config with one preset active
+    fn witheq(gains: [f64; 10], loudnorm: bool) -> Config {
+        Config {
+            eq: String::from("test"),
+            eqpresets: vec![EqPreset::new("test", gains)],
+            loudnorm,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn flathasnofilters() {
+        assert_eq!(audiofilters(&Config::default()), "");
+        assert_eq!(audiofilters(&witheq([0.0; 10], false)), "");
+    }
+
+    #[test]
+    fn flatbandsareskipped() {
+        let config = witheq([3.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.5], false);
+        assert_eq!(audiofilters(&config), "lavfi=[equalizer=f=31:t=o:w=1:g=3,equalizer=f=16000:t=o:w=1:g=-1.5]");
+    }
+
+    #[test]
+    fn loudnormcomesaftertheequalizer() {
+        let config = witheq([0.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0], true);
+        assert_eq!(audiofilters(&config), "lavfi=[equalizer=f=62:t=o:w=1:g=2],lavfi=[loudnorm=I=-16:TP=-1.5:LRA=11]");
+    }
+
+    #[test]
+    fn unknownpresetsareflat() {
+        let config = Config { eq: String::from("gone"), ..Config::default() };
+        assert_eq!(activepreset(&config).name, "flat");
+    }
+}
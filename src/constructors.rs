@@ -1,25 +1,100 @@
 use ratatui::{
-    layout::{Constraint, Direction, Layout, Rect}, style::{Color, Style}, text::{Line, Text}, widgets::{Block, BorderType, Borders, Gauge, List, ListItem, Paragraph}, Frame
+    layout::{Constraint, Direction, Layout, Rect}, style::{Color, Modifier, Style}, text::{Line, Text}, widgets::{Block, BorderType, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph}, Frame
 };
 
-use crate::consts::{App, CurrentColumn, Track};
+use crate::{actions, art, audio, chapters, config, sleep};
+use crate::bookmarks::AbLoop;
+use crate::consts::{App, Chapter, CurrentColumn, LayoutMode, PlaylistKind, PromptKind, StatusLevel, StatusLog, Track};
 
 static SONGINFOPERCENT: u16 = 70;
+static STATUSSECS: u64 = 8;         // how long a status message stays in the credits line
+static MINWIDTH: u16 = 24;          // smallest usable terminal width
+static MINHEIGHT: u16 = 9;          // smallest usable terminal height
+static STANDARDWIDTH: u16 = 60;     // width at which the three column view kicks in
+static STANDARDHEIGHT: u16 = 10;    // height at which the three column view kicks in
+static WIDEWIDTH: u16 = 150;        // width at which the side panels are added
+static WIDEHEIGHT: u16 = 20;        // height at which the side panels are added
+
+/// areas of the main view
+///
+/// columns that do not exist in the current layout mode are None
+#[derive(Clone, Copy, Debug)]
+pub struct Areas {
+    pub mode: LayoutMode,
+    pub playlists: Option<Rect>,
+    pub tracks: Option<Rect>,
+    pub queue: Rect,            // in minimal mode this shows the focused column
+    pub controls: Option<Rect>,
+    pub songinfo: Rect,
+    pub progressbar: Rect,
+    pub credits: Rect,
+    pub nowplaying: Option<Rect>,
+    pub history: Option<Rect>,
+}
+
+/// picks a layout mode based on the size of the terminal
+///
+/// # arguments
+/// * 'area' - the area of the terminal
+///
+/// # returns
+/// * the layout mode, or None if the terminal is too small to draw anything useful
+pub fn getlayoutmode(area: Rect) -> Option<LayoutMode> {
+    if area.width < MINWIDTH || area.height < MINHEIGHT {
+        None
+    } else if area.width < STANDARDWIDTH || area.height < STANDARDHEIGHT {
+        Some(LayoutMode::Minimal)
+    } else if area.width >= WIDEWIDTH && area.height >= WIDEHEIGHT {
+        Some(LayoutMode::Wide)
+    } else {
+        Some(LayoutMode::Standard)
+    }
+}
 
 /// main constructor
 /// 
 /// # arguments
 /// * 'area' - the area to split up into individual areas
+/// * 'isplaying' - whether music is playing (the controls box is one wider when it is)
 /// 
 /// # returns
-/// * a tuple of six rects:
-///     * playlists
-///     * tracks
-///     * queue
-///     * controls
-///     * songinfo
-///     * progressbar
-pub fn construct(area: Rect, isplaying: &bool) -> (Rect, Rect, Rect, Rect, Rect, Rect, Rect) {
+/// * the areas for the layout mode that fits the terminal, or None if it is too small
+pub fn construct(area: Rect, isplaying: &bool) -> Option<Areas> {
+    match getlayoutmode(area)? {
+        LayoutMode::Minimal => Some(constructminimal(area)),
+        LayoutMode::Standard => Some(constructstandard(area, isplaying, LayoutMode::Standard)),
+        LayoutMode::Wide => Some(constructstandard(area, isplaying, LayoutMode::Wide)),
+    }
+}
+
+/// PRIVATE constructor for the compact one column player
+fn constructminimal(area: Rect) -> Areas {
+    let verticalchunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),      // song info
+                Constraint::Length(3),      // progress bar
+                Constraint::Min(3),         // focused column
+                Constraint::Length(1)       // credits
+            ])
+            .split(area);
+
+    Areas {
+        mode: LayoutMode::Minimal,
+        playlists: None,
+        tracks: None,
+        queue: verticalchunks[2],
+        controls: None,
+        songinfo: verticalchunks[0],
+        progressbar: verticalchunks[1],
+        credits: verticalchunks[3],
+        nowplaying: None,
+        history: None,
+    }
+}
+
+/// PRIVATE constructor for the three column view, with side panels in wide mode
+fn constructstandard(area: Rect, isplaying: &bool, mode: LayoutMode) -> Areas {
     let verticalchunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -32,14 +107,26 @@ pub fn construct(area: Rect, isplaying: &bool) -> (Rect, Rect, Rect, Rect, Rect,
     let toplayout = verticalchunks[0];
     let bottomlayout = verticalchunks[1];
 
-    let topchunks = Layout::default()
+    let topchunks = if mode == LayoutMode::Wide {
+        Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Fill(2),            // playlists
+                Constraint::Fill(5),            // tracks
+                Constraint::Fill(2),            // queue
+                Constraint::Fill(3)             // side panels
+            ])
+            .split(toplayout)
+    } else {
+        Layout::default()
             .direction(Direction::Horizontal)
             .constraints([
                 Constraint::Fill(2),            // playlists
                 Constraint::Fill(5),            // tracks
                 Constraint::Fill(2)             // queue
             ])
-            .split(toplayout);
+            .split(toplayout)
+    };
 
     let bottomchunks = Layout::default()
             .direction(Direction::Horizontal)
@@ -56,27 +143,40 @@ pub fn construct(area: Rect, isplaying: &bool) -> (Rect, Rect, Rect, Rect, Rect,
             )
             .split(bottomlayout);
 
-    let playlists = topchunks[0];
-    let tracks = topchunks[1];
-    let queue = topchunks[2];
-
-    let controls = bottomchunks[0];
-    let songname = bottomchunks[1];
-    let progressbar = bottomchunks[2];
-
-    let credits = verticalchunks[2];
-
-    (playlists, tracks, queue, controls, songname, progressbar, credits)
+    let (nowplaying, history) = if mode == LayoutMode::Wide {
+        let sidechunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Fill(3),            // now playing
+                Constraint::Fill(2)             // history
+            ])
+            .split(topchunks[3]);
+        (Some(sidechunks[0]), Some(sidechunks[1]))
+    } else {
+        (None, None)
+    };
+
+    Areas {
+        mode,
+        playlists: Some(topchunks[0]),
+        tracks: Some(topchunks[1]),
+        queue: topchunks[2],
+        controls: Some(bottomchunks[0]),
+        songinfo: bottomchunks[1],
+        progressbar: bottomchunks[2],
+        credits: verticalchunks[2],
+        nowplaying,
+        history,
+    }
 }
 
-fn getcontrolscont(app: &App) -> Paragraph {
+fn getcontrolscont(app: &App) -> Paragraph<'static> {
     // gets controls
-    let controls;
-    if app.playing {
-        controls = "[<<] [ pause ] [>>]";
+    let controls = if app.playing {
+        "[<<] [ pause ] [>>]"
     } else {
-        controls = "[<<] [ play ] [>>]";
-    }
+        "[<<] [ play ] [>>]"
+    };
 
     Paragraph::new(controls)
         .block(
@@ -89,11 +189,16 @@ fn getcontrolscont(app: &App) -> Paragraph {
         .alignment(ratatui::layout::Alignment::Center)
 }
 
-fn getplaylistscont(playlists: &Vec<crate::consts::Playlist>, infocus: bool) -> List {
+fn getplaylistscont(playlists: &[crate::consts::Playlist], infocus: bool) -> List<'static> {
     // gets the list of playlists
     let playlistitems: Vec<ListItem> = playlists
         .iter()
-        .map(|p| ListItem::new(format!(" {}", p.name.as_str())))
+        .map(|p| match p.kind {
+            PlaylistKind::Stations => ListItem::new(format!(" {} (radio)", p.name.as_str())),
+            PlaylistKind::Podcast => ListItem::new(format!(" {} (podcast)", p.name.as_str())),
+            PlaylistKind::Smart => ListItem::new(format!(" {} (smart)", p.name.as_str())),
+            PlaylistKind::Tracks => ListItem::new(format!(" {}", p.name.as_str())),
+        })
         .collect();
 
     let playlistslist = List::new(playlistitems)
@@ -128,11 +233,20 @@ fn getplaylistscont(playlists: &Vec<crate::consts::Playlist>, infocus: bool) ->
     playlistslist
 }
 
-fn gettrackscont(tracks: &Vec<crate::consts::Track>, infocus: bool) -> List {
+fn gettrackscont(tracks: &[Track], infocus: bool) -> List<'static> {
     // gets the list of tracks
     let trackitems: Vec<ListItem> = tracks
         .iter()
-        .map(|t| ListItem::new(format!(" {} - {}", t.title.as_str(), t.artist.as_str())))
+        .map(|t| {
+            if t.isepisode() {
+                // unplayed episodes are marked like in other podcast apps
+                let state = if t.played { " " } else { "•" };
+                let downloaded = if t.download.is_some() { " ↓" } else { "" };
+                ListItem::new(format!(" {} {}{}", state, t.displaytitle(), downloaded))
+            } else {
+                ListItem::new(format!(" {} - {}", t.displaytitle(), t.artist.as_str()))
+            }
+        })
         .collect();
 
     let trackslist = List::new(trackitems)
@@ -167,11 +281,11 @@ fn gettrackscont(tracks: &Vec<crate::consts::Track>, infocus: bool) -> List {
     trackslist
 }
 
-fn getqueuecont(queue: &Vec<Track>, infocus: bool) -> List<'static> {
+fn getqueuecont(queue: &[Track], infocus: bool) -> List<'static> {
     // gets the play queue
     let queueitems: Vec<ListItem> = queue
         .iter()
-        .map(|t| ListItem::new(format!(" {}", t.title.as_str())))
+        .map(|t| ListItem::new(format!(" {}", t.displaytitle())))
         .collect();
 
     let queuelist = List::new(queueitems)
@@ -206,13 +320,14 @@ fn getqueuecont(queue: &Vec<Track>, infocus: bool) -> List<'static> {
     queuelist
 }
 
-fn getcontrolsstate(shuffle: bool, repeat: crate::consts::RepeatType) -> String {
+fn getcontrolsstate(shuffle: crate::consts::ShuffleMode, repeat: crate::consts::RepeatType) -> String {
     // gets the state of shuffle and repeat 
     let mut controls: Vec<String> = Vec::new();
-    if shuffle {
-        controls.push(String::from("shuffle on ─")); // extra dash so the text stays still call me a sigma
-    } else {
-        controls.push(String::from("shuffle off "));
+    match shuffle {
+        // extra dashes so the text stays still call me a sigma
+        crate::consts::ShuffleMode::Off => controls.push(String::from("shuffle off ─")),
+        crate::consts::ShuffleMode::Random => controls.push(String::from("shuffle on ──")),
+        crate::consts::ShuffleMode::Smart => controls.push(String::from("shuffle smart"))
     }
 
     match repeat {
@@ -224,25 +339,25 @@ fn getcontrolsstate(shuffle: bool, repeat: crate::consts::RepeatType) -> String
     controls.join("──")
 }
 
-fn getsonginfocont(queue: &Vec<Track>, currentqueueidx: u32, shuffle: bool, repeat: crate::consts::RepeatType) -> Paragraph<'static> {
+fn getsonginfocont(queue: &[Track], currentqueueidx: u32, streamtitle: Option<&str>, shuffle: crate::consts::ShuffleMode, repeat: crate::consts::RepeatType, eq: String) -> Paragraph<'static> {
     // gets currently playing song
-    let displaytext: String;
-
     // --- check if the index points to a valid track ---
     let trackidx = currentqueueidx as usize;
-    if !queue.is_empty() && trackidx < queue.len() {
+    let displaytext = if !queue.is_empty() && trackidx < queue.len() {
         // --- valid track ---
         let currenttrack = &queue[trackidx];
 
-        displaytext = if !currenttrack.artist.is_empty() {
-            format!(" {} - {}", currenttrack.artist, currenttrack.title)
+        if let (true, Some(streamtitle)) = (currenttrack.islive(), streamtitle) {
+            format!(" {} ── {}", currenttrack.displaytitle(), streamtitle)
+        } else if !currenttrack.artist.is_empty() {
+            format!(" {} - {}", currenttrack.artist, currenttrack.displaytitle())
         } else {
-            format!(" {}", currenttrack.title)
-        };
+            format!(" {}", currenttrack.displaytitle())
+        }
     } else {
         // --- no valid track (empty queue or invalid index) ---
-        displaytext = " no song playing ".to_string();
-    }
+        " no song playing ".to_string()
+    };
 
     // --- get controls state string ---
     let controlsstatestring = getcontrolsstate(shuffle, repeat);
@@ -254,6 +369,7 @@ fn getsonginfocont(queue: &Vec<Track>, currentqueueidx: u32, shuffle: bool, repe
             Block::default()
                 .title_top(" currently playing ")
                 .title_top(controlsstateline)
+                .title_bottom(ratatui::text::Line::from(format!(" {} ", eq)).right_aligned())
                 .border_type(BorderType::Rounded)
                 .borders(Borders::ALL),
         )
@@ -272,52 +388,218 @@ fn getprettyduration(secs: u32) -> String {
     format!("{}:{:02}", minutes, seconds)
 }
 
-fn getprogressbar(currentprogresssecs: u32, totalsecs: u32) -> Gauge<'static> {
-    // gets the progressbar 
+fn getprogressbar(currentprogresssecs: u32, totalsecs: u32, chapterlist: &[Chapter], abloop: &AbLoop) -> Gauge<'static> {
+    // gets the progressbar, labelled with the chapter playing
     let currentprogress: String = getprettyduration(currentprogresssecs);
     let totalprogress: String = getprettyduration(totalsecs);
-    let currentprogressratio;
-    if totalsecs == 0 {
-        currentprogressratio = 0f64;
+    let looping = match (abloop.a, abloop.b) {
+        (Some(a), Some(b)) => format!("── loop {}-{} ", getprettyduration(a), getprettyduration(b)),
+        (Some(a), None) => format!("── loop {}- ", getprettyduration(a)),
+        _ => String::new(),
+    };
+    let currentprogressratio = if totalsecs == 0 {
+        0f64
     } else {
-        currentprogressratio = currentprogresssecs as f64/totalsecs as f64;
-    }
+        currentprogresssecs as f64/totalsecs as f64
+    };
 
     Gauge::default()
         .block(
-            Block::default().title(format!(" {}/{} ", currentprogress, totalprogress))
+            Block::default().title(format!(" {}/{} {}", currentprogress, totalprogress, looping))
                 .borders(Borders::ALL)
                 .border_type(BorderType::Rounded)
         )
         .style(Style::default().fg(Color::Magenta))
         .gauge_style(Style::default().fg(Color::LightMagenta))
-        .label("")
+        .label(chapters::currentchapter(chapterlist, currentprogresssecs)
+            .map(|idx| chapterlist[idx].title.clone())
+            .unwrap_or_default())
         .ratio(currentprogressratio)
 }
 
-fn getcreditscont(version: &str) -> Block<'static> {
-    // gets the credits
-    Block::new()
-        .title_top(format!("mpvlayer ── v{} ", version))
+/// PRIVATE function that gets the progress bar of a live stream, which fills up and shows how long it has been on
+fn getlivebar(elapsedsecs: u32) -> Gauge<'static> {
+    Gauge::default()
+        .block(
+            Block::default().title(format!(" LIVE ── {} ", getprettyduration(elapsedsecs)))
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+        )
+        .style(Style::default().fg(Color::Magenta))
+        .gauge_style(Style::default().fg(Color::LightMagenta))
+        .label("LIVE")
+        .ratio(1.0)
+}
+
+/// PRIVATE function that marks chapters, bookmarks and the a-b loop on the progress bar
+fn drawmarkers(frame: &mut Frame, area: Rect, track: &Track, abloop: &AbLoop) {
+    // inside the border
+    let inner = Rect::new(area.x + 1, area.y + 1, area.width.saturating_sub(2), area.height.saturating_sub(2));
+    let totalsecs = track.duration;
+    if totalsecs == 0 || inner.width == 0 || inner.height == 0 {
+        return;
+    }
+    let column = |secs: u32| inner.x + (secs.min(totalsecs - 1) as u64 * inner.width as u64 / totalsecs as u64) as u16;
+    let buffer = frame.buffer_mut();
+
+    // the loop is underlined so the chapter and bookmark markers still show inside it
+    if let Some(a) = abloop.a {
+        let end = abloop.b.map(column).unwrap_or(column(a));
+        for x in column(a)..=end {
+            if let Some(cell) = buffer.cell_mut((x, inner.y)) {
+                cell.set_style(Style::default().fg(Color::Cyan).add_modifier(Modifier::UNDERLINED));
+            }
+        }
+    }
+
+    let chaptermarks = track.chapters.iter().filter(|c| c.start > 0).map(|c| (c.start, "│"));
+    let bookmarkmarks = track.bookmarks.iter().map(|b| (b.position, "▾"));
+    for (secs, symbol) in chaptermarks.chain(bookmarkmarks).filter(|(secs, _)| *secs < totalsecs) {
+        if let Some(cell) = buffer.cell_mut((column(secs), inner.y)) {
+            cell.set_symbol(symbol);
+        }
+    }
+}
+
+fn getcreditscont(version: &str, status: &StatusLog, timers: &[String]) -> Block<'static> {
+    // gets the credits, with the latest status message in the middle for a few seconds
+    let mut left = format!("mpvlayer ── v{} ── ? help ", version);
+    for timer in timers {
+        left.push_str(&format!("── {} ", timer));
+    }
+    let mut credits = Block::new()
+        .title_top(left)
         .title_top(Line::from(" https://github.com/ellipticobj/mpvlayer").right_aligned())
         .border_type(BorderType::Rounded)
-        .borders(Borders::TOP)
+        .borders(Borders::TOP);
+
+    if let Some(message) = status.messages.back().filter(|m| m.time.elapsed().as_secs() < STATUSSECS) {
+        let style = match message.level {
+            StatusLevel::Info => Style::default().fg(Color::Magenta),
+            StatusLevel::Error => Style::default().fg(Color::Red),
+        };
+        credits = credits.title_top(Line::styled(format!(" {} ", message.text), style).centered());
+    }
+
+    credits
+}
+
+/// PRIVATE function that describes the sleep timer and stop after current for the credits
+fn gettimers(app: &App) -> Vec<String> {
+    let mut timers = Vec::new();
+    if let Some(left) = sleep::remaining(&app.sleep) {
+        let action = if app.config.sleepquits { "quit" } else { "sleep" };
+        timers.push(format!("{} {}", action, getprettyduration(left.as_secs() as u32)));
+    }
+    if app.stopaftercurrent {
+        timers.push(String::from("stop after current"));
+    }
+    timers
+}
+
+/// builds the lines of the messages popup, newest first
+/// 
+/// # arguments
+/// * `status` - the status log
+/// 
+/// # returns
+/// * the lines as a Vec<String>
+pub fn getstatuslines(status: &StatusLog) -> Vec<String> {
+    if status.messages.is_empty() {
+        return vec![String::from("no messages")];
+    }
+
+    status.messages
+        .iter()
+        .rev()
+        .map(|m| {
+            let level = match m.level {
+                StatusLevel::Info => "info ",
+                StatusLevel::Error => "error",
+            };
+            format!("{} {:>6} ago  {}", level, getprettyduration(m.time.elapsed().as_secs() as u32), m.text)
+        })
+        .collect()
+}
+
+fn getnowplayingcont(queue: &[Track], currentqueueidx: u32, streamtitle: Option<&str>, gain: String) -> Paragraph<'static> {
+    // gets the details of the currently playing track
+    let trackidx = currentqueueidx as usize;
+    let lines: Vec<Line> = if trackidx < queue.len() {
+        let currenttrack = &queue[trackidx];
+        // a stream shows what is on, with the station in place of the artist
+        let (title, artist) = match (currenttrack.islive(), streamtitle) {
+            (true, Some(streamtitle)) => (streamtitle, currenttrack.displaytitle()),
+            _ => (currenttrack.displaytitle(), currenttrack.artist.as_str()),
+        };
+        let length = if currenttrack.islive() { String::from("live") } else { getprettyduration(currenttrack.duration) };
+        vec![
+            Line::from(format!(" {}", title)),
+            Line::from(format!(" {}", artist)),
+            Line::from(format!(" album    {}", currenttrack.album.as_deref().unwrap_or("-"))),
+            Line::from(format!(" length   {}", length)),
+            Line::from(format!(" plays    {}", currenttrack.playcount)),
+            Line::from(format!(" queue    {}/{}", trackidx + 1, queue.len())),
+            Line::from(format!(" gain     {}", gain)),
+            Line::from(format!(" source   {}", currenttrack.url)),
+        ]
+    } else {
+        vec![Line::from(" no song playing ")]
+    };
+
+    Paragraph::new(lines)
+        .style(Style::default().fg(Color::Magenta))
+        .wrap(ratatui::widgets::Wrap { trim: false })
+}
+
+/// PRIVATE function that renders the now playing panel, with the cover art above the details
+fn rendernowplaying(app: &mut App, frame: &mut Frame, area: Rect) {
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .title(" now playing ")
+        .style(Style::default().fg(Color::Magenta));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),        // art
+            Constraint::Length(9)       // details
+        ])
+        .split(inner);
+
+    art::render(&mut app.ui.art, frame, chunks[0]);
+    let gain = audio::describe(app);
+    frame.render_widget(getnowplayingcont(&app.queue, app.currentqueueidx, app.streamtitle.as_deref(), gain), chunks[1]);
 }
 
-fn windowsizepass(frame: &Frame) -> bool {
-    let termwin = frame.area();
-    let width = termwin.width;
-    let height = termwin.height;
+fn gethistorycont(history: &[Track]) -> List<'static> {
+    // gets the recently played tracks, newest first
+    let historyitems: Vec<ListItem> = history
+        .iter()
+        .rev()
+        .map(|t| ListItem::new(format!(" {} - {}", t.displaytitle(), t.artist.as_str())))
+        .collect();
+
+    List::new(historyitems)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(" history ")
+        )
+}
 
-    // Add more granular size checks
-    if width < 40 {
-        // TODO: minimal mode with reduced features 
-        true
-    } else if width < 60 || height < 10 {
-        false
+/// PRIVATE function that renders the tracks of the selected playlist
+fn rendertracks(app: &mut App, frame: &mut Frame, area: Rect) {
+    let playlistidx = app.ui.playliststate.selected().unwrap_or(0);
+    if playlistidx < app.playlists.len() {
+        let trackscont = gettrackscont(&app.playlists[playlistidx].tracks, app.ui.currentcolumn == CurrentColumn::Tracks);
+        frame.render_stateful_widget(trackscont, area, &mut app.ui.tracksstate);
     } else {
-        // TODO: full mode
-        true
+        frame.render_widget(Block::default().borders(Borders::ALL).title(" tracks "), area);
     }
 }
 
@@ -326,55 +608,86 @@ fn windowsizepass(frame: &Frame) -> bool {
 /// # arguments
 /// * `app` - mutable reference to the app state
 /// * `frame` - mutable reference to the frame to render on
-/// * `areas` - tuple of rectangular areas for different UI components
+/// * `areas` - areas for the current layout mode, None if the terminal is too small
 /// 
 /// # returns
 /// * nothing
-pub fn rendermainview(app: &mut App, frame: &mut Frame, areas: (Rect, Rect, Rect, Rect, Rect, Rect, Rect)) {
-    let sizecheck = windowsizepass(frame);
+pub fn rendermainview(app: &mut App, frame: &mut Frame, areas: Option<Areas>) {
     let isrepeated = app.repeatedinstance;
-    if sizecheck && !isrepeated {
-        let (playlists, tracks, queue, controls, songinfo, progressbar, credits) = areas;
+    if let (Some(areas), false) = (areas, isrepeated) {
+        if let Some(playlists) = areas.playlists {
+            let playlistscont = getplaylistscont(&app.playlists, app.ui.currentcolumn == CurrentColumn::Playlists);
+            frame.render_stateful_widget(playlistscont, playlists, &mut app.ui.playliststate);
+        }
 
-        let playlistscont = getplaylistscont(&app.playlists, app.currentcolumn == CurrentColumn::Playlists);
-        frame.render_stateful_widget(playlistscont, playlists, &mut app.playliststate);
-        
-        if !app.playlists.is_empty() && (app.playliststate.selected().unwrap_or(0)) < app.playlists.len() {
-            let trackscont = gettrackscont(&app.playlists[app.playliststate.selected().unwrap_or(0)].tracks, app.currentcolumn == CurrentColumn::Tracks);
-            frame.render_stateful_widget(trackscont, tracks, &mut app.tracksstate);
-        } else {
-            frame.render_widget(Block::default().borders(Borders::ALL).title(" tracks "), tracks);
+        if let Some(tracks) = areas.tracks {
+            rendertracks(app, frame, tracks);
         }
 
-        let controlscont = getcontrolscont(app);
-        frame.render_widget(controlscont, controls);
+        if let Some(controls) = areas.controls {
+            let controlscont = getcontrolscont(app);
+            frame.render_widget(controlscont, controls);
+        }
 
-        let songinfocont = getsonginfocont(&app.queue, app.currentqueueidx, app.shuffle, app.repeat);
-        frame.render_widget(songinfocont, songinfo);
+        let songinfocont = getsonginfocont(&app.queue, app.currentqueueidx, app.streamtitle.as_deref(), app.shuffle, app.repeat, audio::describeeq(app));
+        frame.render_widget(songinfocont, areas.songinfo);
 
-        let creditscont = getcreditscont(&app.version);
-        frame.render_widget(creditscont, credits);
+        let creditscont = getcreditscont(&app.version, &app.status, &gettimers(app));
+        frame.render_widget(creditscont, areas.credits);
 
-        let progressbarcont;
         // if queue is not empty and current index is valid and current duration is valid
-        if !app.queue.is_empty() && (app.currentqueueidx as usize) < app.queue.len() && app.currentdurationsecs <= app.queue[app.currentqueueidx as usize].duration {
-            progressbarcont = getprogressbar(app.currentdurationsecs, app.queue[app.currentqueueidx as usize].duration);
+        if app.queue.get(app.currentqueueidx as usize).is_some_and(|t| t.islive()) {
+            frame.render_widget(getlivebar(app.currentdurationsecs), areas.progressbar);
+        } else if !app.queue.is_empty() && (app.currentqueueidx as usize) < app.queue.len() && app.currentdurationsecs <= app.queue[app.currentqueueidx as usize].duration {
+            let track = &app.queue[app.currentqueueidx as usize];
+            frame.render_widget(getprogressbar(app.currentdurationsecs, track.duration, &track.chapters, &app.abloop), areas.progressbar);
+            drawmarkers(frame, areas.progressbar, track, &app.abloop);
         } else {
-            progressbarcont = getprogressbar(0, 0);
+            frame.render_widget(getprogressbar(0, 0, &[], &app.abloop), areas.progressbar);
+        }
+
+        // in minimal mode the single list shows whichever column is focused
+        match (areas.mode, app.ui.currentcolumn) {
+            (LayoutMode::Minimal, CurrentColumn::Playlists) => {
+                let playlistscont = getplaylistscont(&app.playlists, true);
+                frame.render_stateful_widget(playlistscont, areas.queue, &mut app.ui.playliststate);
+            }
+            (LayoutMode::Minimal, CurrentColumn::Tracks) => rendertracks(app, frame, areas.queue),
+            _ => {
+                let queuecont = getqueuecont(&app.queue, app.ui.currentcolumn == CurrentColumn::Queue);
+                frame.render_stateful_widget(queuecont, areas.queue, &mut app.ui.queuestate);
+            }
+        }
+
+        // only fetch art while there is somewhere to show it
+        app.ui.art.wanted = areas.nowplaying.is_some();
+        if let Some(nowplaying) = areas.nowplaying {
+            rendernowplaying(app, frame, nowplaying);
         }
-        frame.render_widget(progressbarcont, progressbar);
 
-        let queuecont = getqueuecont(&app.queue, app.currentcolumn == CurrentColumn::Queue);
-        frame.render_stateful_widget(queuecont, queue, &mut app.queuestate);
+        if let Some(history) = areas.history {
+            let historycont = gethistorycont(&app.history);
+            frame.render_widget(historycont, history);
+        }
 
-        // render popup after everything else
+        // render popup and palette after everything else
         renderpopup(app, frame);
+        renderpalette(app, frame);
+        renderequalizer(app, frame);
+        renderbookmarks(app, frame);
+        rendermaintenance(app, frame);
     } else if isrepeated {
         let area = frame.area();
         let displaytext = vec![
             Line::from("Another instance of mpvlayer is already running"),
             Line::from(""),
-            Line::from("Press Enter to quit")
+            Line::from("You can control it from here:"),
+            Line::from("[space] play/pause   [<] previous   [>] next"),
+            Line::from("[s] shuffle   [r] repeat"),
+            Line::from(""),
+            Line::from(app.ui.remotestatus.clone()),
+            Line::from(""),
+            Line::from("Press Enter or q to quit")
         ];
         
         let cont = Paragraph::new(displaytext)
@@ -391,7 +704,7 @@ pub fn rendermainview(app: &mut App, frame: &mut Frame, areas: (Rect, Rect, Rect
         let area = frame.area();
         let displaytext = vec![
             Line::from("window needs to be"),
-            Line::from("at least 60x10")
+            Line::from(format!("at least {}x{}", MINWIDTH, MINHEIGHT))
         ];
         
         let cont = Paragraph::new(displaytext)
@@ -418,13 +731,18 @@ pub fn rendermainview(app: &mut App, frame: &mut Frame, areas: (Rect, Rect, Rect
 /// # returns
 /// * the centered rect
 pub fn centerrect(rect: Rect, area: Rect) -> Rect {
-    let x = (area.width - rect.width) / 2;
-    let y = (area.height - rect.height) / 2;
-    Rect::new(x, y, rect.width, rect.height)
+    // shrink the rect if the area is too small to hold it (minimal mode)
+    let width = std::cmp::min(rect.width, area.width);
+    let height = std::cmp::min(rect.height, area.height);
+    let x = area.x + (area.width - width) / 2;
+    let y = area.y + (area.height - height) / 2;
+    Rect::new(x, y, width, height)
 }
 
 /// PRIVATE function that renders a popup
 /// 
+/// the popup grows to fit its longest line and scrolls if it is taller than the screen
+/// 
 /// # arguments
 /// * `app` - mutable reference to the app state
 /// * `frame` - mutable reference to the frame to render on
@@ -432,54 +750,271 @@ pub fn centerrect(rect: Rect, area: Rect) -> Rect {
 /// # returns
 /// * nothing
 fn renderpopup(app: &mut App, frame: &mut Frame) {
-    if app.popup.onscreen {
+    if app.ui.popup.onscreen {
         let area = frame.area();
-        let popuparea = centerrect(Rect::new(0, 0, 40, 10), area);
-        let popupcont = Paragraph::new(Text::from(app.popup.message.clone().iter().map(|s| Line::from(s.clone())).collect::<Vec<Line>>()))
+        let longest = app.ui.popup.message.iter().map(|s| s.chars().count()).max().unwrap_or(0) as u16;
+        let width = std::cmp::max(40, longest.saturating_add(4));
+        let height = std::cmp::max(10, (app.ui.popup.message.len() as u16).saturating_add(2));
+        let popuparea = centerrect(Rect::new(0, 0, width, height), area);
+        let popupcont = Paragraph::new(Text::from(app.ui.popup.message.iter().map(|s| Line::from(s.clone())).collect::<Vec<Line>>()))
             .block(
                 Block::default()
                     .border_type(BorderType::Rounded)
                     .borders(Borders::ALL)
-                    .title(app.popup.title.clone())
+                    .title(app.ui.popup.title.clone())
             )
             .style(Style::default().fg(Color::Magenta))
             .alignment(ratatui::layout::Alignment::Center)
-            .wrap(ratatui::widgets::Wrap { trim: true });
+            .wrap(ratatui::widgets::Wrap { trim: true })
+            .scroll((app.ui.popup.scroll, 0));
 
+        frame.render_widget(Clear, popuparea);
         frame.render_widget(popupcont, popuparea);
     }
 }
 
-/// creates a popup
+/// PRIVATE function that renders the command palette
 /// 
 /// # arguments
 /// * `app` - mutable reference to the app state
 /// * `frame` - mutable reference to the frame to render on
+/// 
+/// # returns
+/// * nothing
+fn renderpalette(app: &mut App, frame: &mut Frame) {
+    if app.ui.palette.onscreen {
+        let area = frame.area();
+        let matches = if app.ui.palette.prompt.is_some() {
+            Vec::new()
+        } else {
+            actions::matchactions(&app.ui.palette.input)
+        };
+        let height = std::cmp::min(matches.len() as u16 + 3, 14);
+        let mut palettearea = centerrect(Rect::new(0, 0, 50, height), area);
+        // sit near the top like other palettes, but never past the bottom of a short terminal
+        palettearea.y = std::cmp::min(area.y + area.height / 6, area.bottom().saturating_sub(palettearea.height));
+
+        let block = Block::default()
+            .border_type(BorderType::Rounded)
+            .borders(Borders::ALL)
+            .title(match app.ui.palette.prompt {
+                Some(PromptKind::AddUrl) => " add url to playlist ",
+                Some(PromptKind::SleepTimer) => " sleep in how many minutes? ",
+                Some(PromptKind::Bookmark(_)) => " name this bookmark ",
+                Some(PromptKind::NewStationList) => " name the station list ",
+                Some(PromptKind::Subscribe) => " podcast feed url ",
+                Some(PromptKind::NewSmartPlaylist) => " name: rules, like 'short: length < 3:00' ",
+                None => " command palette ",
+            })
+            .style(Style::default().fg(Color::Magenta));
+        let inner = block.inner(palettearea);
+        frame.render_widget(Clear, palettearea);
+        frame.render_widget(block, palettearea);
+
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(1),      // input
+                Constraint::Min(0)          // matches
+            ])
+            .split(inner);
+
+        frame.render_widget(Paragraph::new(format!(":{}_", app.ui.palette.input)), chunks[0]);
+
+        let matchitems: Vec<ListItem> = matches
+            .iter()
+            .map(|info| {
+                let keys = info.keys.iter().map(actions::keyname).collect::<Vec<String>>().join(" / ");
+                ListItem::new(format!(" {:<28}{:>12}", info.name, keys))
+            })
+            .collect();
+        let matchlist = List::new(matchitems)
+            .highlight_style(Style::default().bg(Color::Magenta).fg(Color::White))
+            .highlight_symbol("> ");
+        let mut matchstate = ListState::default();
+        if !matches.is_empty() {
+            matchstate.select(Some(std::cmp::min(app.ui.palette.selected, matches.len() - 1)));
+        }
+        frame.render_stateful_widget(matchlist, chunks[1], &mut matchstate);
+    }
+}
+
+/// PRIVATE function that draws one equalizer band as a bar centred on 0 dB
+fn geteqbar(gain: f64) -> String {
+    let half = 6;
+    let filled = std::cmp::min(gain.abs().round() as usize, half);
+    let (left, right) = if gain < 0.0 { (filled, 0) } else { (0, filled) };
+    format!(
+        "{}{}│{}{}",
+        " ".repeat(half - left),
+        "█".repeat(left),
+        "█".repeat(right),
+        " ".repeat(half - right)
+    )
+}
+
+/// PRIVATE function that renders the equalizer, presets on the left and their bands on the right
+/// 
+/// # arguments
+/// * `app` - mutable reference to the app state
+/// * `frame` - mutable reference to the frame to render on
+/// 
+/// # returns
+/// * nothing
+fn renderequalizer(app: &mut App, frame: &mut Frame) {
+    if app.ui.equalizer.onscreen {
+        let presets = config::eqpresets(&app.config);
+        let selected = std::cmp::min(app.ui.equalizer.selected, presets.len() - 1);
+        let height = std::cmp::max(presets.len(), config::EQBANDS.len()) as u16 + 4;
+        let eqarea = centerrect(Rect::new(0, 0, 58, height), frame.area());
+
+        let block = Block::default()
+            .border_type(BorderType::Rounded)
+            .borders(Borders::ALL)
+            .title(" equalizer ")
+            .title_bottom(Line::from(" [enter] keep  [esc] cancel ").right_aligned())
+            .style(Style::default().fg(Color::Magenta));
+        let inner = block.inner(eqarea);
+        frame.render_widget(Clear, eqarea);
+        frame.render_widget(block, eqarea);
+
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Length(20),     // presets
+                Constraint::Min(0)          // bands
+            ])
+            .split(inner);
+
+        let presetitems: Vec<ListItem> = presets.iter().map(|p| ListItem::new(format!(" {}", p.name))).collect();
+        let presetlist = List::new(presetitems)
+            .highlight_style(Style::default().bg(Color::Magenta).fg(Color::White))
+            .highlight_symbol("> ");
+        let mut presetstate = ListState::default();
+        presetstate.select(Some(selected));
+        frame.render_stateful_widget(presetlist, chunks[0], &mut presetstate);
+
+        let bandlines: Vec<Line> = config::EQBANDS
+            .iter()
+            .enumerate()
+            .map(|(idx, freq)| {
+                let gain = presets[selected].gains.get(idx).copied().unwrap_or(0.0);
+                let label = if *freq >= 1000 { format!("{}k", freq / 1000) } else { freq.to_string() };
+                Line::from(format!("{:>4} {} {:+.0}dB", label, geteqbar(gain), gain))
+            })
+            .collect();
+        frame.render_widget(Paragraph::new(bandlines), chunks[1]);
+    }
+}
+
+/// PRIVATE function that renders the bookmarks of the current track
+/// 
+/// # arguments
+/// * `app` - mutable reference to the app state
+/// * `frame` - mutable reference to the frame to render on
+/// 
+/// # returns
+/// * nothing
+fn renderbookmarks(app: &mut App, frame: &mut Frame) {
+    if app.ui.bookmarks.onscreen {
+        let bookmarks = app.queue.get(app.currentqueueidx as usize).map(|t| t.bookmarks.as_slice()).unwrap_or(&[]);
+        let height = std::cmp::min(bookmarks.len().max(1) as u16 + 2, 16);
+        let bookmarkarea = centerrect(Rect::new(0, 0, 44, height), frame.area());
+
+        let block = Block::default()
+            .border_type(BorderType::Rounded)
+            .borders(Borders::ALL)
+            .title(" bookmarks ")
+            .title_bottom(Line::from(" [enter] jump  [d] delete ").right_aligned())
+            .style(Style::default().fg(Color::Magenta));
+        let inner = block.inner(bookmarkarea);
+        frame.render_widget(Clear, bookmarkarea);
+        frame.render_widget(block, bookmarkarea);
+
+        if bookmarks.is_empty() {
+            frame.render_widget(Paragraph::new(" no bookmarks, press m to add one"), inner);
+            return;
+        }
+
+        let items: Vec<ListItem> = bookmarks
+            .iter()
+            .map(|b| ListItem::new(format!(" {:<30}{:>7}", b.name, getprettyduration(b.position))))
+            .collect();
+        let list = List::new(items)
+            .highlight_style(Style::default().bg(Color::Magenta).fg(Color::White))
+            .highlight_symbol("> ");
+        let mut state = ListState::default();
+        state.select(Some(std::cmp::min(app.ui.bookmarks.selected, bookmarks.len() - 1)));
+        frame.render_stateful_widget(list, inner, &mut state);
+    }
+}
+
+/// PRIVATE function that renders the maintenance view, duplicates and dead links in the library
+/// 
+/// # arguments
+/// * `app` - mutable reference to the app state
+/// * `frame` - mutable reference to the frame to render on
+/// 
+/// # returns
+/// * nothing
+fn rendermaintenance(app: &mut App, frame: &mut Frame) {
+    if app.ui.maintenance.onscreen {
+        let lines = &app.ui.maintenance.report;
+        let area = frame.area();
+        let maintenancearea = centerrect(Rect::new(0, 0, std::cmp::min(80, area.width), area.height.saturating_sub(4)), area);
+        // the report can shrink after a removal, so the scroll is clamped here as well
+        let scroll = std::cmp::min(app.ui.maintenance.scroll, lines.len().saturating_sub(1) as u16);
+
+        let block = Block::default()
+            .border_type(BorderType::Rounded)
+            .borders(Borders::ALL)
+            .title(" library maintenance ")
+            .title_bottom(Line::from(match app.ui.maintenance.confirming {
+                Some('d') => " press d again to remove duplicates within each playlist, any other key cancels ",
+                Some('D') => " press D again to keep one copy of each track in the whole library, any other key cancels ",
+                Some('x') => " press x again to remove dead tracks from every playlist, any other key cancels ",
+                _ => " [c] check links  [d] remove duplicates  [D] across playlists  [x] remove dead ",
+            }).right_aligned())
+            .style(Style::default().fg(Color::Magenta));
+        frame.render_widget(Clear, maintenancearea);
+        frame.render_widget(
+            Paragraph::new(lines.iter().map(|l| Line::from(format!(" {}", l))).collect::<Vec<Line>>())
+                .block(block)
+                .scroll((scroll, 0)),
+            maintenancearea,
+        );
+    }
+}
+
+/// creates a popup
+/// 
+/// # arguments
+/// * `app` - mutable reference to the app state
 /// * `title` - the title of the popup
 /// * `message` - the message of the popup
+/// * `dangerous` - whether closing the popup quits the app
 /// 
 /// # returns
 /// * nothing
-pub fn showpopup(app: &mut App, frame: &mut Frame, title: String, message: Vec<String>, dangerous: bool) {
-    app.popup.onscreen = true;
-    app.popup.title = title;
-    app.popup.message = message;
-    app.popup.dangerous = dangerous;
-    renderpopup(app, frame);
+pub fn showpopup(app: &mut App, title: String, message: Vec<String>, dangerous: bool) {
+    app.ui.popup.onscreen = true;
+    app.ui.popup.title = title;
+    app.ui.popup.message = message;
+    app.ui.popup.dangerous = dangerous;
+    app.ui.popup.scroll = 0;
 }
 
 /// clears a popup
 ///
 /// # arguments
 /// * `app` - mutable reference to the app state
-/// * `frame` - mutable reference to the frame to render on
 /// 
 /// # returns
 /// * nothing
-pub fn clearpopup(app: &mut App, frame: &mut Frame) {
-    app.popup.onscreen = false;
-    app.popup.title = String::from("");
-    app.popup.message = Vec::new();
-    app.popup.dangerous = false;
-    renderpopup(app, frame);
+pub fn clearpopup(app: &mut App) {
+    app.ui.popup.onscreen = false;
+    app.ui.popup.title = String::from("");
+    app.ui.popup.message = Vec::new();
+    app.ui.popup.dangerous = false;
+    app.ui.popup.scroll = 0;
 }
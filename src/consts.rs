@@ -1,23 +1,267 @@
-use std::process::Child;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
+use std::collections::VecDeque;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use ratatui::widgets::ListState;
+use ratatui_image::picker::Picker;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
+use crate::instance::ControlServer;
+use crate::art::ArtState;
+use crate::config::Config;
+use crate::crossfade::Outgoing;
+use crate::feeds::FeedUpdater;
+use crate::maintenance::LinkChecker;
+use crate::metadata::Resolver;
+use crate::sponsorblock::Skipper;
+use crate::player::Player;
+use crate::bookmarks::AbLoop;
+use crate::sleep::SleepTimer;
+use crate::smart::Rule;
+use crate::supervisor::Supervisor;
 
 pub static MAXQUEUELENGTH: usize = 50;
-pub static MPVSOCKET: &str = "/tmp/mpvsocket";
-pub static LOCKPATH: &str = "/tmp/mpvlayer.lock";
+pub static MAXHISTORYLENGTH: usize = 50;
+pub static MAXSTATUSMESSAGES: usize = 100;
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    #[default]
+    Url,        // any other url mpv can play
+    YouTube,    // a youtube video
+    File,       // a file on disk
+    Stream,     // a live stream or radio station, it has no duration
+    Episode     // a podcast episode from a feed
+}
+
+/// a named part of a track, like a song in a mix
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Chapter {
+    pub title: String,
+    pub start: u32          // seconds into the track
+}
+
+/// a named position in a track
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bookmark {
+    pub name: String,
+    pub position: u32       // seconds into the track
+}
+
+/// a playable track
+///
+/// tracks are identified by `id` (the video id or a hash of the file), not by their other fields.
+/// every field has a default so libraries saved by older versions still load
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Track {
     pub title: String,
     pub artist: String,
     pub duration: u32,
-    pub url: String
+    pub url: String,
+
+    pub id: String,                 // stable id, see Track::getid
+    pub source: SourceKind,         // where the track comes from
+    pub album: Option<String>,
+    pub thumbnail: Option<String>,  // url of the thumbnail, if known
+    pub added: Option<u64>,         // unix timestamp of when the track was added
+    pub playcount: u32,             // how many times the track has been played
+    pub lastplayed: Option<u64>,    // unix timestamp of when the track was last played
+    pub rating: Option<u8>,         // user rating out of 5
+    pub chapters: Vec<Chapter>,     // chapters, sorted by start
+    pub bookmarks: Vec<Bookmark>,   // bookmarks, sorted by position
+    pub played: bool,               // listened to the end, used for podcast episodes
+    pub resumeat: u32,              // seconds to carry on from next time, 0 to start over, see Track::resumable
+    pub download: Option<String>    // path of a downloaded copy, played instead of the url
+}
+
+impl PartialEq for Track {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug)]
+impl Eq for Track {}
+
+impl Hash for Track {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl Track {
+    /// creates a track and works out its id and source from the url
+    /// 
+    /// # arguments
+    /// * 'title' - title of the track
+    /// * 'artist' - artist of the track
+    /// * 'duration' - length of the track in seconds, 0 if unknown
+    /// * 'url' - url or path of the track
+    /// 
+    /// # returns
+    /// * the track
+    pub fn new(title: &str, artist: &str, duration: u32, url: &str) -> Self {
+        let mut track = Track {
+            title: String::from(title),
+            artist: String::from(artist),
+            duration,
+            url: String::from(url),
+            added: Some(unixtime()),
+            ..Track::default()
+        };
+        track.fillmissing();
+        track
+    }
+
+    /// gets the title to display, falling back to the url until metadata is resolved
+    pub fn displaytitle(&self) -> &str {
+        if self.title.is_empty() {
+            &self.url
+        } else {
+            &self.title
+        }
+    }
+
+    /// checks if the track is a live stream, which never ends by itself
+    pub fn islive(&self) -> bool {
+        self.source == SourceKind::Stream
+    }
+
+    /// checks if the track is a podcast episode
+    pub fn isepisode(&self) -> bool {
+        self.source == SourceKind::Episode
+    }
+
+    /// checks if the track remembers where it was left, podcast episodes always do
+    /// 
+    /// # arguments
+    /// * 'minsecs' - how long other tracks have to be, 0 if they never do
+    /// 
+    /// # returns
+    /// * whether it resumes
+    pub fn resumable(&self, minsecs: u32) -> bool {
+        self.isepisode() || (minsecs > 0 && !self.islive() && self.duration >= minsecs)
+    }
+
+    /// gets what to hand to the player, the downloaded copy if there is one
+    pub fn playurl(&self) -> &str {
+        match &self.download {
+            Some(path) if std::path::Path::new(path).exists() => path,
+            _ => &self.url,
+        }
+    }
+
+    /// fills in the id and source of tracks that do not have one (e.g. loaded from an old library)
+    pub fn fillmissing(&mut self) {
+        if self.id.is_empty() {
+            let (id, source) = Track::getid(&self.url);
+            self.id = id;
+            self.source = source;
+        }
+    }
+
+    /// gets the stable id and source kind of a url
+    /// 
+    /// youtube videos use their video id, files use a hash of their contents
+    /// and anything else uses a hash of the url
+    /// 
+    /// # arguments
+    /// * 'url' - url or path of the track
+    /// 
+    /// # returns
+    /// * a tuple of the id and the source kind
+    pub fn getid(url: &str) -> (String, SourceKind) {
+        if let Some(videoid) = youtubeid(url) {
+            return (videoid, SourceKind::YouTube);
+        }
+
+        if let Ok(file) = File::open(url) {
+            // hash the length and the first MiB, hashing whole files would stall on big mixes
+            let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+            let mut head = len.to_le_bytes().to_vec();
+            if file.take(1024 * 1024).read_to_end(&mut head).is_ok() {
+                return (format!("file:{:016x}", stablehash(&head)), SourceKind::File);
+            }
+        }
+
+        (format!("url:{:016x}", stablehash(url.as_bytes())), SourceKind::Url)
+    }
+}
+
+/// gets the video id from a youtube url
+/// 
+/// # arguments
+/// * 'url' - the url
+/// 
+/// # returns
+/// * the video id, or None if the url is not a youtube video
+pub fn youtubeid(url: &str) -> Option<String> {
+    let rest = url.split_once("://").map(|(_, rest)| rest).unwrap_or(url);
+    let rest = rest.strip_prefix("www.").or_else(|| rest.strip_prefix("m.")).or_else(|| rest.strip_prefix("music.")).unwrap_or(rest);
+
+    let id = if let Some(path) = rest.strip_prefix("youtu.be/") {
+        path.split(['?', '&', '#']).next()
+    } else if let Some(query) = rest.strip_prefix("youtube.com/watch?") {
+        query.split('&').find_map(|param| param.strip_prefix("v="))
+    } else if let Some(path) = rest.strip_prefix("youtube.com/shorts/") {
+        path.split(['?', '&', '#']).next()
+    } else {
+        None
+    }?;
+
+    if id.is_empty() {
+        None
+    } else {
+        Some(String::from(id))
+    }
+}
+
+/// hashes bytes with 64 bit fnv-1a
+/// 
+/// used for ids that are saved to disk, std's hasher can change between rust releases
+/// 
+/// # arguments
+/// * 'bytes' - the bytes to hash
+/// 
+/// # returns
+/// * the hash
+pub fn stablehash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// gets the current unix time in seconds
+pub fn unixtime() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PlaylistKind {
+    #[default]
+    Tracks,
+    Stations,   // radio stations, urls added to it are live streams
+    Podcast,    // episodes of a podcast, kept up to date from its feed
+    Smart       // every track in the library that meets its rules
+}
+
+#[derive(PartialEq, Eq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Playlist {
     pub name: String,
-    pub tracks: Vec<Track>
+    pub tracks: Vec<Track>,
+    pub kind: PlaylistKind,
+    pub feed: Option<String>,   // url of the rss or atom feed of a podcast
+    pub rules: Vec<Rule>        // rules of a smart playlist, its tracks are worked out from them
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum ShuffleMode {
+    Off,
+    Random,     // everything after the current track in a random order
+    Smart       // random, but same artist tracks are spread out and recently played ones go last
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -34,12 +278,129 @@ pub enum CurrentColumn {
     Queue
 }
 
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum LayoutMode {
+    Minimal,    // one column player for small panes
+    Standard,   // playlists, tracks and queue columns
+    Wide        // standard plus now playing and history panels
+}
+
 #[derive(Debug)]
 pub struct PopupState {
     pub onscreen: bool,
     pub dangerous: bool,
     pub title: String,
-    pub message: Vec<String>
+    pub message: Vec<String>,
+    pub scroll: u16
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PromptKind {
+    AddUrl,         // url of a track to add to the selected playlist
+    SleepTimer,     // minutes until playback pauses
+    Bookmark(u32),  // name of a bookmark at this position
+    NewStationList, // name of a new playlist of radio stations
+    Subscribe,      // url of a podcast feed
+    NewSmartPlaylist // name and rules of a smart playlist
+}
+
+#[derive(Debug, Default)]
+pub struct PaletteState {
+    pub onscreen: bool,
+    pub input: String,                  // what has been typed after the ':'
+    pub selected: usize,                // index into the matching actions
+    pub prompt: Option<PromptKind>      // set when the palette is asking for text instead of an action
+}
+
+#[derive(Debug, Default)]
+pub struct EqualizerState {
+    pub onscreen: bool,
+    pub selected: usize,                // index into the presets
+    pub previous: String                // preset that was active when the view opened, restored on esc
+}
+
+#[derive(Debug, Default)]
+pub struct MaintenanceState {
+    pub onscreen: bool,
+    pub scroll: u16,
+    pub confirming: Option<char>,   // key of a bulk removal waiting to be pressed again
+    pub report: Vec<String>,        // lines shown, worked out when the library or a link check changes
+}
+
+#[derive(Debug, Default)]
+pub struct BookmarkListState {
+    pub onscreen: bool,
+    pub selected: usize,                // index into the current track's bookmarks
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum StatusLevel {
+    Info,
+    Error
+}
+
+#[derive(Clone, Debug)]
+pub struct StatusMessage {
+    pub level: StatusLevel,
+    pub text: String,
+    pub time: Instant       // when the message was added
+}
+
+/// non fatal messages shown in the status line, newest last
+#[derive(Debug, Default)]
+pub struct StatusLog {
+    pub messages: VecDeque<StatusMessage>
+}
+
+impl StatusLog {
+    /// adds a message, dropping the oldest once there are MAXSTATUSMESSAGES
+    pub fn push(&mut self, level: StatusLevel, text: String) {
+        self.messages.push_back(StatusMessage { level, text, time: Instant::now() });
+        if self.messages.len() > MAXSTATUSMESSAGES {
+            self.messages.pop_front();
+        }
+    }
+}
+
+/// what is on screen, kept apart from the app so playback logic does not depend on it
+#[derive(Debug)]
+pub struct UiState {
+    pub currentcolumn: CurrentColumn, // currently selected column (track, playlist, queue)
+    pub playliststate: ListState,   // currently selecetd playlist
+    pub tracksstate: ListState,     // currently selected track
+    pub queuestate: ListState,      // currently selected track in queue
+
+    pub remotestatus: String,       // result of the last command sent to the running instance
+    pub popup: PopupState,          // popup 
+    pub palette: PaletteState,      // command palette
+    pub equalizer: EqualizerState,  // equalizer preset picker
+    pub bookmarks: BookmarkListState, // bookmarks of the current track
+    pub maintenance: MaintenanceState, // duplicates and dead links
+    pub art: ArtState,              // cover art of the current track
+}
+
+impl UiState {
+    pub fn new(picker: Picker) -> Self {
+        UiState {
+            currentcolumn: CurrentColumn::Playlists,
+            playliststate: ListState::default(),
+            tracksstate: ListState::default(),
+            queuestate: ListState::default(),
+            remotestatus: String::new(),
+            popup: PopupState {
+                onscreen: false,
+                dangerous: false,
+                title: String::from(""),
+                message: Vec::new(),
+                scroll: 0
+            },
+            palette: PaletteState::default(),
+            equalizer: EqualizerState::default(),
+            bookmarks: BookmarkListState::default(),
+            maintenance: MaintenanceState::default(),
+            art: ArtState::new(picker),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -53,22 +414,100 @@ pub struct App {
     pub queue: Vec<Track>,                      // queue of tracks
     pub queuebeforeshuffle: Option<Vec<Track>>, // queue before shuffle is active
     pub queuebeforerepeat: Option<Vec<Track>>,  // original queue
+    pub history: Vec<Track>,                    // recently played tracks, oldest first
 
     pub currentqueueidx: u32,                   // index of currently playing track in teh queue
     pub currentplaylistidx: u32,                // index of playlist that the currently playing song is in
     pub currentdurationsecs: u32,               // elapsed duration in the currently playing track
 
-    pub shuffle: bool,      // shuffle state
+    pub shuffle: ShuffleMode, // shuffle state
     pub repeat: RepeatType, // repeat state
 
-    pub mpv: Option<Child>, // mpv process
+    pub player: Box<dyn Player>,    // plays the current track, mpv outside of tests
+    pub supervisor: Supervisor,     // restarts the player when it crashes
+    pub preloadedidx: Option<u32>,  // queue index the player will move on to by itself
+    pub preloadpending: bool,       // the player has not been told what comes next yet
+    pub outgoing: Option<Outgoing>, // the previous track while crossfading into the current one
+    pub fadefrom: Option<f64>,      // volume before fading started, None if untouched
+    pub fadevolume: Option<u32>,    // percent of fadefrom last set while fading
+    pub chaptersfetched: bool,      // the player was asked for the current track's chapters
+    pub streamtitle: Option<String>, // what a live stream says is playing, from its icy metadata
+    pub stopaftercurrent: bool,     // stop instead of moving on when the current track ends
+    pub sleep: SleepTimer,          // pauses playback after a while
+    pub abloop: AbLoop,             // part of the current track to loop
 
-    pub currentcolumn: CurrentColumn, // currently selected column (track, playlist, queue)
-    pub playliststate: ListState,  // currently selecetd playlist
-    pub tracksstate: ListState,     // currently selected track
-    pub queuestate: ListState,      // currently selected track in queue
+    pub config: Config,                 // settings from the config file
 
-    pub lockfile: Option<File>,     // lock file for single instance check
-    pub popup: PopupState,          // popup 
+    pub lockfile: Option<File>,         // lock file for single instance check
+    pub control: Option<ControlServer>, // receives commands from other instances
+    pub status: StatusLog,              // recent errors and notices for the status line
+    pub metadata: Resolver,             // looks up missing track metadata
+    pub sponsorblock: Skipper,          // fetches segments of youtube videos to skip
+    pub feeds: FeedUpdater,             // keeps podcasts up to date and downloads episodes
+    pub links: LinkChecker,             // checks the library for dead links
+
+    pub ui: UiState,                    // selections, popups and art
+}
+
+impl App {
+    /// creates the app in its stopped state
+    /// 
+    /// # arguments
+    /// * 'playlists' - the library
+    /// * 'config' - the settings
+    /// * 'player' - what plays the tracks
+    /// * 'picker' - graphics protocol for cover art
+    /// 
+    /// # returns
+    /// * the app
+    pub fn new(playlists: Vec<Playlist>, config: Config, player: Box<dyn Player>, picker: Picker) -> Self {
+        App {
+            running: true,
+            playing: false,
+            version: String::from("0.0.1"),
+            repeatedinstance: false,
+            playlists,
+            queue: Vec::new(),
+            queuebeforeshuffle: None,
+            queuebeforerepeat: None,
+            history: Vec::new(),
+            currentqueueidx: 0,
+            currentplaylistidx: 0,
+            currentdurationsecs: 0,
+            shuffle: ShuffleMode::Off,
+            repeat: RepeatType::None,
+            player,
+            supervisor: Supervisor::default(),
+            preloadedidx: None,
+            preloadpending: false,
+            outgoing: None,
+            fadefrom: None,
+            fadevolume: None,
+            chaptersfetched: false,
+            streamtitle: None,
+            stopaftercurrent: false,
+            sleep: SleepTimer::default(),
+            abloop: AbLoop::default(),
+            config,
+            lockfile: None,
+            control: None,
+            status: StatusLog::default(),
+            metadata: Resolver::new(),
+            sponsorblock: Skipper::new(),
+            feeds: FeedUpdater::new(),
+            links: LinkChecker::new(),
+            ui: UiState::new(picker),
+        }
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idsdonotchangebetweenbuilds() {
+        assert_eq!(stablehash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(Track::getid("https://example.com/a.mp3"), (String::from("url:e4c12665bf413a37"), SourceKind::Url));
+    }
+}
@@ -0,0 +1,187 @@
+use std::time::Instant;
+use anyhow::Result;
+use crate::{backend, sleep};
+use crate::consts::App;
+use crate::player::Player;
+
+/// the track that fades out while the next one fades in, on a player of its own
+#[derive(Debug)]
+pub struct Outgoing {
+    pub player: Box<dyn Player>,    // still playing the end of the old track
+    pub from: f64,                  // its volume when the crossfade started
+    pub startedat: Instant,         // when the crossfade started
+}
+
+/// starts the next track on a second player once the current one is close enough to its end
+///
+/// the next track comes from the queue like it would at the end of the track, so shuffle and
+/// repeat still apply. tracks shorter than two crossfades just play through. called every second
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn check(app: &mut App) -> Result<()> {
+    let secs = app.config.crossfade;
+    if secs == 0 || !app.playing || app.outgoing.is_some() || app.stopaftercurrent {
+        return Ok(());
+    }
+    let Some(track) = app.queue.get(app.currentqueueidx as usize) else {
+        return Ok(());
+    };
+    if track.islive() || track.duration <= 2 * secs || backend::nextidx(app).is_none() {
+        return Ok(());
+    }
+    let duration = track.duration;
+    let position = app.player.position()?;
+    if position + secs < duration {
+        return Ok(());
+    }
+
+    // the old player must not move on to the next track by itself while it fades out
+    app.player.preload(None)?;
+    let from = match app.fadefrom {
+        Some(from) => from,
+        None => app.player.getproperty("volume").ok().and_then(|v| v.as_f64()).unwrap_or(100.0),
+    };
+    let incoming = app.player.another();
+    let player = std::mem::replace(&mut app.player, incoming);
+    let previous = app.currentqueueidx;
+
+    log::info!("crossfading over {}s", secs);
+    if let Err(e) = backend::trackended(app) {
+        // the next track did not start, let the current one finish instead
+        app.player = player;
+        app.currentqueueidx = previous;
+        app.ui.queuestate.select(Some(previous as usize));
+        app.playing = true;
+        return Err(e);
+    }
+    app.outgoing = Some(Outgoing { player, from, startedat: Instant::now() });
+    Ok(())
+}
+
+/// fades the old track out while crossfading, called every tick
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn update(app: &mut App) -> Result<()> {
+    let sleepfactor = sleep::volumefactor(app);
+    let secs = app.config.crossfade.max(1) as f64;
+    let Some(outgoing) = app.outgoing.as_mut() else {
+        return Ok(());
+    };
+
+    let left = 1.0 - outgoing.startedat.elapsed().as_secs_f64() / secs;
+    // pausing or stopping would leave it playing on its own
+    if left <= 0.0 || !app.playing || outgoing.player.poll().is_some() || !outgoing.player.isrunning() {
+        cut(app);
+        return Ok(());
+    }
+    outgoing.player.setproperty("volume", serde_json::json!(outgoing.from * left * sleepfactor))?;
+    Ok(())
+}
+
+/// stops the old track of a crossfade straight away
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn cut(app: &mut App) {
+    if let Some(mut outgoing) = app.outgoing.take() {
+        log::debug!("crossfade done");
+        outgoing.player.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::*;
+    use crate::testing::{entertrack, testapp};
+
+    /// moves the start of the crossfade back, as if it had been going for secs
+    fn elapse(app: &mut App, secs: f64) {
+        if let Some(outgoing) = app.outgoing.as_mut() {
+            outgoing.startedat = Instant::now() - Duration::from_secs_f64(secs);
+        }
+    }
+
+    #[test]
+    fn thenexttrackstartsonasecondplayerandtheoldonefadesout() {
+        let mut app = testapp(3);
+        app.config.crossfade = 4;
+        entertrack(&mut app, 0);
+        app.player.setproperty("volume", serde_json::json!(80)).unwrap();
+
+        app.player.setproperty("time-pos", serde_json::json!(90)).unwrap();
+        check(&mut app).unwrap();
+        assert!(app.outgoing.is_none());
+
+        app.player.setproperty("time-pos", serde_json::json!(96)).unwrap();
+        check(&mut app).unwrap();
+        assert_eq!(app.currentqueueidx, 1);
+        assert!(app.player.isrunning());
+        assert_eq!(app.player.position().unwrap(), 0);
+        assert_eq!(app.history.len(), 2);
+
+        // both play at once, the old one without anything after it
+        let outgoing = app.outgoing.as_mut().unwrap();
+        assert!(outgoing.player.isrunning());
+        assert_eq!(outgoing.player.position().unwrap(), 96);
+        assert_eq!(outgoing.from, 80.0);
+
+        elapse(&mut app, 1.0);
+        update(&mut app).unwrap();
+        let volume = app.outgoing.as_mut().unwrap().player.getproperty("volume").unwrap().as_f64().unwrap();
+        assert_eq!(volume.round(), 60.0);
+
+        elapse(&mut app, 4.0);
+        update(&mut app).unwrap();
+        assert!(app.outgoing.is_none());
+        assert_eq!(app.currentqueueidx, 1);
+    }
+
+    #[test]
+    fn nocrossfadewhennothingcomesnext() {
+        let mut app = testapp(2);
+        app.config.crossfade = 4;
+        entertrack(&mut app, 1);
+        app.player.setproperty("time-pos", serde_json::json!(97)).unwrap();
+        check(&mut app).unwrap();
+        assert!(app.outgoing.is_none());
+
+        entertrack(&mut app, 0);
+        app.stopaftercurrent = true;
+        app.player.setproperty("time-pos", serde_json::json!(97)).unwrap();
+        check(&mut app).unwrap();
+        assert!(app.outgoing.is_none());
+    }
+
+    #[test]
+    fn pausingorskippingcutstheoldtrack() {
+        let mut app = testapp(3);
+        app.config.crossfade = 4;
+        entertrack(&mut app, 0);
+        app.player.setproperty("time-pos", serde_json::json!(97)).unwrap();
+        check(&mut app).unwrap();
+        assert!(app.outgoing.is_some());
+
+        backend::togglepause(&mut app).unwrap();
+        update(&mut app).unwrap();
+        assert!(app.outgoing.is_none());
+
+        backend::togglepause(&mut app).unwrap();
+        app.player.setproperty("time-pos", serde_json::json!(97)).unwrap();
+        check(&mut app).unwrap();
+        assert!(app.outgoing.is_some());
+        backend::playnexttrack(&mut app).unwrap();
+        assert!(app.outgoing.is_none());
+    }
+}
@@ -0,0 +1,45 @@
+use std::fmt;
+use std::io;
+
+/// errors from talking to mpv and the other programs mpvlayer drives
+#[derive(Debug)]
+pub enum BackendError {
+    Spawn { program: String, url: String, source: io::Error },  // a program could not be started
+    Ipc { command: String, source: io::Error },                 // mpv's socket could not be reached or read
+    Mpv { command: String, error: String },                     // mpv replied with an error
+    BadReply { command: String, reply: String },                // mpv replied with something that is not json
+}
+
+pub type BackendResult<T> = std::result::Result<T, BackendError>;
+
+impl BackendError {
+    /// checks if the error leaves mpvlayer unable to do anything useful
+    /// 
+    /// only a missing mpv binary counts, everything else is shown in the status line
+    pub fn isfatal(&self) -> bool {
+        matches!(self, BackendError::Spawn { program, source, .. } if program == "mpv" && source.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackendError::Spawn { program, url, source } if source.kind() == io::ErrorKind::NotFound => {
+                write!(f, "{} is not installed (needed to play '{}')", program, url)
+            }
+            BackendError::Spawn { program, url, source } => write!(f, "failed to spawn {} for '{}': {}", program, url, source),
+            BackendError::Ipc { command, source } => write!(f, "could not reach mpv for '{}': {}", command, source),
+            BackendError::Mpv { command, error } => write!(f, "mpv rejected '{}': {}", command, error),
+            BackendError::BadReply { command, reply } => write!(f, "mpv sent a bad reply to '{}': {}", command, reply),
+        }
+    }
+}
+
+impl std::error::Error for BackendError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BackendError::Spawn { source, .. } | BackendError::Ipc { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,423 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use roxmltree::{Document, Node};
+use crate::consts::{stablehash, App, Playlist, PlaylistKind, SourceKind, StatusLevel, Track};
+use crate::{library, paths, smart};
+
+static TIMEOUTSECS: u64 = 30;
+
+/// a podcast as read from its feed
+#[derive(Clone, Debug, Default)]
+pub struct Feed {
+    pub title: String,
+    pub episodes: Vec<Track>,   // newest first, like feeds list them
+}
+
+/// what the background threads send back
+#[derive(Debug)]
+enum FeedResult {
+    Fetched(String, Result<Feed>),          // feed url and what it contained
+    Downloaded(String, Result<PathBuf>),    // track id and where it was saved
+}
+
+/// fetches feeds on a background thread and downloads episodes on their own threads
+#[derive(Debug)]
+pub struct FeedUpdater {
+    requests: Sender<String>,               // feed urls for the worker to fetch
+    results: Receiver<FeedResult>,          // what the worker and downloads found
+    downloads: Sender<FeedResult>,          // handed to download threads
+    inflight: HashSet<String>,              // feed urls and track ids not back yet
+    lastrefresh: Option<Instant>,           // when every feed was last requested
+}
+
+impl FeedUpdater {
+    /// creates an updater and starts its worker thread
+    pub fn new() -> Self {
+        let (requests, workerrequests) = mpsc::channel::<String>();
+        let (workerresults, results) = mpsc::channel();
+        let downloads = workerresults.clone();
+
+        thread::spawn(move || {
+            for url in workerrequests {
+                let feed = fetch(&url).and_then(|xml| parsefeed(&xml));
+                if workerresults.send(FeedResult::Fetched(url, feed)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        FeedUpdater {
+            requests,
+            results,
+            downloads,
+            inflight: HashSet::new(),
+            lastrefresh: None,
+        }
+    }
+}
+
+/// gets the directory downloaded episodes are kept in
+pub fn downloaddir() -> PathBuf {
+    paths::cachedir().join("episodes")
+}
+
+/// PRIVATE function that gets a new http agent that gives up on slow servers
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new().timeout(Duration::from_secs(TIMEOUTSECS)).build()
+}
+
+/// fetches a feed
+///
+/// # arguments
+/// * 'url' - url of the feed
+///
+/// # returns
+/// * the feed's xml
+pub fn fetch(url: &str) -> Result<String> {
+    log::debug!("fetching feed {}", url);
+    Ok(agent().get(url).call()?.into_string()?)
+}
+
+/// PRIVATE function that gets the first child element with a name, ignoring namespaces
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.is_element() && n.tag_name().name() == name)
+}
+
+/// PRIVATE function that gets the trimmed text of a child element, None if it is missing or empty
+fn childtext(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|n| n.text())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+}
+
+/// PRIVATE function that parses an itunes:duration, which is seconds, m:ss or h:mm:ss
+fn parseduration(text: &str) -> u32 {
+    text.split(':')
+        .try_fold(0u32, |total, part| part.trim().parse::<f64>().ok().map(|p| total * 60 + p as u32))
+        .unwrap_or(0)
+}
+
+/// PRIVATE function that makes a track out of an episode
+///
+/// episodes are identified by their guid, the url of the audio often changes between fetches
+fn episode(title: String, author: String, duration: u32, url: String, guid: Option<String>, image: Option<String>) -> Track {
+    let key = guid.as_deref().unwrap_or(&url);
+    let mut track = Track::new(&title, &author, duration, &url);
+    track.id = format!("episode:{:016x}", stablehash(key.as_bytes()));
+    track.source = SourceKind::Episode;
+    track.thumbnail = image;
+    track
+}
+
+/// parses an rss or atom feed
+///
+/// entries without audio are left out
+///
+/// # arguments
+/// * 'xml' - the feed
+///
+/// # returns
+/// * the feed
+pub fn parsefeed(xml: &str) -> Result<Feed> {
+    let document = Document::parse(xml)?;
+    let root = document.root_element();
+    match root.tag_name().name() {
+        "rss" => {
+            let channel = child(root, "channel").ok_or_else(|| anyhow::anyhow!("rss feed has no channel"))?;
+            let title = childtext(channel, "title").unwrap_or_default();
+            let author = childtext(channel, "author").unwrap_or_else(|| title.clone());
+            let image = child(channel, "image").and_then(|i| i.attribute("href").map(String::from).or_else(|| childtext(i, "url")));
+
+            let episodes = channel.children()
+                .filter(|n| n.is_element() && n.tag_name().name() == "item")
+                .filter_map(|item| {
+                    let url = child(item, "enclosure")?.attribute("url")?.to_string();
+                    Some(episode(
+                        childtext(item, "title").unwrap_or_else(|| url.clone()),
+                        childtext(item, "author").unwrap_or_else(|| author.clone()),
+                        childtext(item, "duration").map(|d| parseduration(&d)).unwrap_or(0),
+                        url,
+                        childtext(item, "guid"),
+                        child(item, "image").and_then(|i| i.attribute("href")).map(String::from).or_else(|| image.clone()),
+                    ))
+                })
+                .collect();
+            Ok(Feed { title, episodes })
+        }
+        "feed" => {
+            let title = childtext(root, "title").unwrap_or_default();
+            let author = child(root, "author").and_then(|a| childtext(a, "name")).unwrap_or_else(|| title.clone());
+
+            let episodes = root.children()
+                .filter(|n| n.is_element() && n.tag_name().name() == "entry")
+                .filter_map(|entry| {
+                    let url = entry.children()
+                        .find(|n| n.tag_name().name() == "link" && n.attribute("rel") == Some("enclosure"))?
+                        .attribute("href")?
+                        .to_string();
+                    Some(episode(
+                        childtext(entry, "title").unwrap_or_else(|| url.clone()),
+                        child(entry, "author").and_then(|a| childtext(a, "name")).unwrap_or_else(|| author.clone()),
+                        0,
+                        url,
+                        childtext(entry, "id"),
+                        None,
+                    ))
+                })
+                .collect();
+            Ok(Feed { title, episodes })
+        }
+        other => Err(anyhow::anyhow!("not a feed, it starts with <{}>", other)),
+    }
+}
+
+/// adds the episodes of a feed that a podcast playlist does not have yet
+///
+/// episodes already in the playlist are left alone so their played state and position are kept
+///
+/// # arguments
+/// * 'playlist' - the podcast's playlist
+/// * 'feed' - the feed that was just fetched
+///
+/// # returns
+/// * how many episodes were added
+pub fn merge(playlist: &mut Playlist, feed: Feed) -> usize {
+    // the placeholder name given when subscribing
+    if playlist.feed.as_deref() == Some(playlist.name.as_str()) && !feed.title.is_empty() {
+        playlist.name = feed.title;
+    }
+
+    let new: Vec<Track> = feed.episodes.into_iter().filter(|e| !playlist.tracks.contains(e)).collect();
+    let count = new.len();
+    playlist.tracks.splice(0..0, new);
+    count
+}
+
+/// PRIVATE function that asks the worker for a feed
+fn request(app: &mut App, url: &str) {
+    if app.feeds.inflight.contains(url) {
+        return;
+    }
+    if app.feeds.requests.send(String::from(url)).is_ok() {
+        app.feeds.inflight.insert(String::from(url));
+    }
+}
+
+/// subscribes to a podcast, its episodes show up once the feed is fetched
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'url' - url of the feed
+///
+/// # returns
+/// * none
+pub fn subscribe(app: &mut App, url: &str) -> Result<()> {
+    if let Some(playlist) = app.playlists.iter().find(|p| p.feed.as_deref() == Some(url)) {
+        return Err(anyhow::anyhow!("already subscribed to {}", playlist.name));
+    }
+
+    log::info!("subscribing to {}", url);
+    app.playlists.push(Playlist {
+        name: String::from(url),
+        tracks: Vec::new(),
+        kind: PlaylistKind::Podcast,
+        feed: Some(String::from(url)),
+        ..Playlist::default()
+    });
+    request(app, url);
+    app.status.push(StatusLevel::Info, String::from("subscribed, fetching episodes"));
+    Ok(())
+}
+
+/// checks every podcast for new episodes
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn refresh(app: &mut App) {
+    app.feeds.lastrefresh = Some(Instant::now());
+    let urls: Vec<String> = app.playlists.iter().filter_map(|p| p.feed.clone()).collect();
+    for url in urls {
+        request(app, &url);
+    }
+}
+
+/// downloads an episode into the cache, it is played from there once it is done
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'track' - the episode
+///
+/// # returns
+/// * none
+pub fn download(app: &mut App, track: &Track) -> Result<()> {
+    if !track.isepisode() {
+        return Err(anyhow::anyhow!("only podcast episodes can be downloaded"));
+    }
+    if app.feeds.inflight.contains(&track.id) {
+        return Ok(());
+    }
+
+    // keep the extension so mpv and ffprobe do not have to guess
+    let extension = track.url
+        .split(['?', '#']).next()
+        .and_then(|path| path.rsplit_once('.'))
+        .map(|(_, ext)| ext)
+        .filter(|ext| ext.len() <= 4 && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("mp3");
+    let path = downloaddir().join(format!("{}.{}", track.id.replace(':', "-"), extension));
+
+    let (id, url, results) = (track.id.clone(), track.url.clone(), app.feeds.downloads.clone());
+    app.feeds.inflight.insert(id.clone());
+    app.status.push(StatusLevel::Info, format!("downloading {}", track.displaytitle()));
+    thread::spawn(move || {
+        let result = (|| -> Result<PathBuf> {
+            fs::create_dir_all(downloaddir())?;
+            // written next to the final path and moved, so a half finished download is never played
+            let partial = path.with_extension("part");
+            let mut file = fs::File::create(&partial)?;
+            io::copy(&mut agent().get(&url).call()?.into_reader(), &mut file)?;
+            fs::rename(&partial, &path)?;
+            Ok(path)
+        })();
+        let _ = results.send(FeedResult::Downloaded(id, result));
+    });
+    Ok(())
+}
+
+/// writes finished fetches and downloads into the library, and refreshes feeds when it is time
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn update(app: &mut App) {
+    let refreshevery = Duration::from_secs(app.config.feedrefresh.max(1) as u64 * 60);
+    if app.feeds.lastrefresh.is_none_or(|last| last.elapsed() >= refreshevery) {
+        refresh(app);
+    }
+
+    while let Ok(result) = app.feeds.results.try_recv() {
+        match result {
+            FeedResult::Fetched(url, Ok(feed)) => {
+                app.feeds.inflight.remove(&url);
+                let Some(playlist) = app.playlists.iter_mut().find(|p| p.feed.as_deref() == Some(url.as_str())) else {
+                    continue;
+                };
+                let added = merge(playlist, feed);
+                log::info!("{} new episodes of {}", added, playlist.name);
+                if added > 0 {
+                    let message = format!("{} new episodes of {}", added, playlist.name);
+                    app.status.push(StatusLevel::Info, message);
+                    smart::refresh(app);
+                }
+            }
+            FeedResult::Fetched(url, Err(e)) => {
+                app.feeds.inflight.remove(&url);
+                log::warn!("could not fetch {}: {}", url, e);
+                app.status.push(StatusLevel::Error, format!("could not fetch {}: {}", url, e));
+            }
+            FeedResult::Downloaded(id, Ok(path)) => {
+                app.feeds.inflight.remove(&id);
+                let path = path.to_string_lossy().to_string();
+                log::info!("downloaded {} to {}", id, path);
+                library::updatetrack(&mut app.playlists, &id, |t| t.download = Some(path.clone()));
+                for track in app.queue.iter_mut().filter(|t| t.id == id) {
+                    track.download = Some(path.clone());
+                }
+                app.status.push(StatusLevel::Info, String::from("episode downloaded"));
+            }
+            FeedResult::Downloaded(id, Err(e)) => {
+                app.feeds.inflight.remove(&id);
+                log::warn!("could not download {}: {}", id, e);
+                app.status.push(StatusLevel::Error, format!("could not download the episode: {}", e));
+            }
+        }
+    }
+}
+
+/// marks an episode as played or unplayed, either way it starts over next time
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'id' - id of the episode
+/// * 'played' - whether it was played
+///
+/// # returns
+/// * none
+pub fn setplayed(app: &mut App, id: &str, played: bool) {
+    library::edittrack(app, id, |t| {
+        if t.isepisode() {
+            t.played = played;
+            t.resumeat = 0;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{fixture, mockserver};
+
+    #[test]
+    fn rssfeedsareparsed() {
+        let feed = parsefeed(&fixture("podcast.rss")).unwrap();
+        assert_eq!(feed.title, "Loop Station");
+        let titles: Vec<&str> = feed.episodes.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["Episode 3: Sampling & Chopping", "Episode 2: Drums", "Episode 1: Hello"]);
+        // ids are saved in the library, they must not change between builds
+        assert_eq!(feed.episodes[0].id, "episode:d021b1b698ba58be");
+        let durations: Vec<u32> = feed.episodes.iter().map(|e| e.duration).collect();
+        assert_eq!(durations, [3723, 2705, 750]);
+        assert_eq!(feed.episodes[0].artist, "Loop Station Radio");
+        assert_eq!(feed.episodes[1].artist, "A Guest");
+        assert_eq!(feed.episodes[0].thumbnail.as_deref(), Some("https://example.com/cover.jpg"));
+        assert!(feed.episodes.iter().all(|e| e.isepisode()));
+    }
+
+    #[test]
+    fn atomfeedsareparsed() {
+        let feed = parsefeed(&fixture("podcast.atom")).unwrap();
+        assert_eq!(feed.title, "Tape Notes");
+        assert_eq!(feed.episodes.len(), 1);
+        assert_eq!(feed.episodes[0].url, "https://example.com/notes/2.ogg");
+        assert_eq!(feed.episodes[0].artist, "Tape Notes Collective");
+        assert!(parsefeed("<html><body/></html>").is_err());
+    }
+
+    #[test]
+    fn refetchedfeedskeepepisodestate() {
+        let url = "https://example.com/feed.xml";
+        let mut playlist = Playlist { name: String::from(url), feed: Some(String::from(url)), ..Playlist::default() };
+        let mut feed = parsefeed(&fixture("podcast.rss")).unwrap();
+        let newest = feed.episodes.remove(0);
+        assert_eq!(merge(&mut playlist, feed.clone()), 2);
+        assert_eq!(playlist.name, "Loop Station");
+        playlist.tracks[0].played = true;
+
+        // the url of an episode changing does not make it a new one
+        feed.episodes[0].url = String::from("https://cdn.example.com/ep2.m4a");
+        feed.episodes.insert(0, newest);
+        assert_eq!(merge(&mut playlist, feed), 1);
+        assert_eq!(playlist.tracks[0].title, "Episode 3: Sampling & Chopping");
+        assert!(playlist.tracks[1].played);
+    }
+
+    #[test]
+    fn feedsarefetchedoverhttp() {
+        let (url, server) = mockserver("200 OK", &fixture("podcast.atom"));
+        let feed = parsefeed(&fetch(&format!("{}/feed.xml", url)).unwrap()).unwrap();
+        assert_eq!(feed.title, "Tape Notes");
+        assert!(server.join().unwrap().starts_with("GET /feed.xml"));
+    }
+}
@@ -0,0 +1,221 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use fs4::fs_std::FileExt;
+use crate::paths;
+
+/// socket the running instance listens on for commands from other instances
+#[derive(Debug)]
+pub struct ControlServer {
+    listener: UnixListener,
+    pub path: PathBuf,
+}
+
+/// how many times to look for the pid of whoever holds the lock
+static PIDTRIES: u32 = 10;
+/// time between those looks, the holder writes its pid right after locking
+static PIDWAITMS: u64 = 50;
+
+/// PRIVATE function that checks that a pid belongs to a running instance of this program
+fn isalive(pid: i32) -> bool {
+    if pid <= 0 {
+        return false;
+    }
+
+    // signal 0 only checks that the process exists
+    let exists = unsafe { libc::kill(pid, 0) } == 0
+        || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM);
+    if !exists {
+        return false;
+    }
+
+    // a pid can be reused after a crash, check the name where /proc is available
+    match (fs::read_to_string(format!("/proc/{}/comm", pid)), fs::read_to_string("/proc/self/comm")) {
+        (Ok(comm), Ok(ours)) => comm.trim() == ours.trim(),
+        _ => true,
+    }
+}
+
+/// PRIVATE function that reads the pid stored in a lock file
+fn readpid(path: &Path) -> Option<i32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// PRIVATE function that checks whether the pid in a lock file is a running instance
+fn holderalive(path: &Path, tries: u32) -> bool {
+    for attempt in 0..tries {
+        if attempt > 0 {
+            thread::sleep(Duration::from_millis(PIDWAITMS));
+        }
+        if readpid(path).is_some_and(isalive) {
+            return true;
+        }
+    }
+    false
+}
+
+/// takes the single instance lock
+/// 
+/// the lock file is never removed, unlinking it while someone else opens it would let two
+/// instances hold locks on different files. a lock whose pid is not a running instance (held
+/// by a leftover child, or on a filesystem without flock) is stale and is taken over in place
+/// 
+/// # arguments
+/// * none
+/// 
+/// # returns
+/// * the locked file, or None if another instance is running
+pub fn acquirelock() -> Result<Option<File>> {
+    lockat(&paths::lockpath()?)
+}
+
+/// PRIVATE function that takes the lock at a path
+fn lockat(path: &Path) -> Result<Option<File>> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .mode(0o600)
+        .open(path)?;
+
+    let held = match file.try_lock_exclusive() {
+        Ok(locked) => !locked && holderalive(path, PIDTRIES),
+        // without flock the pid is all there is to go on
+        Err(e) => {
+            log::warn!("could not lock {}, going by the pid in it: {}", path.display(), e);
+            holderalive(path, 1)
+        }
+    };
+    if held {
+        return Ok(None);
+    }
+    if let Some(pid) = readpid(path) {
+        log::warn!("taking over the stale lock of pid {} in {}", pid, path.display());
+    }
+
+    file.set_len(0)?;
+    writeln!(&file, "{}", process::id())?;
+    Ok(Some(file))
+}
+
+/// starts listening for commands from other instances
+/// 
+/// # arguments
+/// * none
+/// 
+/// # returns
+/// * the control server
+pub fn listen() -> Result<ControlServer> {
+    let path = paths::controlsocket()?;
+    // we hold the lock, so any socket left here belongs to a dead instance
+    let _ = fs::remove_file(&path);
+
+    let listener = UnixListener::bind(&path)?;
+    listener.set_nonblocking(true)?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+    Ok(ControlServer { listener, path })
+}
+
+/// gets the commands sent since the last poll
+/// 
+/// # arguments
+/// * 'server' - the control server
+/// 
+/// # returns
+/// * the commands, one per line received
+pub fn pollcommands(server: &ControlServer) -> Vec<String> {
+    let mut commands = Vec::new();
+
+    loop {
+        match server.listener.accept() {
+            Ok((stream, _)) => {
+                let _ = stream.set_nonblocking(false);
+                let _ = stream.set_read_timeout(Some(Duration::from_millis(100)));
+                commands.extend(
+                    BufReader::new(stream)
+                        .lines()
+                        .map_while(|l| l.ok())
+                        .map(|l| l.trim().to_string())
+                        .filter(|l| !l.is_empty()),
+                );
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(_) => break,
+        }
+    }
+
+    commands
+}
+
+/// sends a command to the running instance
+/// 
+/// # arguments
+/// * 'command' - name of the action to run
+/// 
+/// # returns
+/// * none
+pub fn sendcommand(command: &str) -> Result<()> {
+    let mut stream = UnixStream::connect(paths::controlsocket()?)?;
+    writeln!(stream, "{}", command)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lockfile(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("mpvlayer-test-{}-{}.lock", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn deadpid() -> i32 {
+        let mut child = process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        child.id() as i32
+    }
+
+    #[test]
+    fn alockheldbyarunninginstanceisleftalone() {
+        let path = lockfile("held");
+        let first = lockat(&path).unwrap();
+        assert!(first.is_some());
+        assert_eq!(readpid(&path), Some(process::id() as i32));
+        assert!(lockat(&path).unwrap().is_none());
+
+        // the file stays when the holder goes, and the next instance takes it
+        drop(first);
+        assert!(path.exists());
+        assert!(lockat(&path).unwrap().is_some());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn astalelockistakenoverinplace() {
+        let path = lockfile("stale");
+        fs::write(&path, format!("{}\n", deadpid())).unwrap();
+
+        // held by something that is not a running instance, like a leftover child
+        let leftover = File::open(&path).unwrap();
+        leftover.try_lock_exclusive().unwrap();
+        assert!(lockat(&path).unwrap().is_some());
+        assert_eq!(readpid(&path), Some(process::id() as i32));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn deadandreusedpidsarenotalive() {
+        assert!(isalive(process::id() as i32));
+        assert!(!isalive(0));
+        assert!(!isalive(deadpid()));
+    }
+}
@@ -0,0 +1,140 @@
+use std::borrow::Cow;
+use std::fs;
+use std::path::PathBuf;
+use anyhow::Result;
+use crate::consts::{App, Playlist, PlaylistKind, Track};
+use crate::paths;
+
+/// gets the path the library is saved at
+pub fn librarypath() -> PathBuf {
+    paths::datadir().join("library.json")
+}
+
+/// loads the saved playlists
+/// 
+/// tracks saved by older versions get their missing ids filled in
+/// 
+/// # arguments
+/// * none
+/// 
+/// # returns
+/// * the playlists, or None if nothing has been saved yet
+pub fn load() -> Result<Option<Vec<Playlist>>> {
+    let path = librarypath();
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let contents = fs::read_to_string(&path)?;
+    let playlists = parse(&contents)
+        .map_err(|e| anyhow::anyhow!("failed to parse library '{}': {}", path.display(), e))?;
+    Ok(Some(playlists))
+}
+
+/// PRIVATE function that reads playlists from the json of a saved library, filling in missing ids
+fn parse(contents: &str) -> Result<Vec<Playlist>> {
+    let mut playlists: Vec<Playlist> = serde_json::from_str(contents)?;
+    for playlist in playlists.iter_mut() {
+        for track in playlist.tracks.iter_mut() {
+            track.fillmissing();
+        }
+    }
+    Ok(playlists)
+}
+
+/// saves the playlists
+/// 
+/// writes to a temporary file first so a crash mid write does not lose the library
+/// 
+/// # arguments
+/// * 'playlists' - the playlists to save
+/// 
+/// # returns
+/// * none
+pub fn save(playlists: &[Playlist]) -> Result<()> {
+    let path = librarypath();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    // smart playlists are filled again on load, saving their tracks would save every match twice
+    let saved: Vec<Cow<Playlist>> = playlists.iter()
+        .map(|p| match p.kind {
+            PlaylistKind::Smart => Cow::Owned(Playlist { name: p.name.clone(), kind: p.kind, rules: p.rules.clone(), ..Playlist::default() }),
+            _ => Cow::Borrowed(p),
+        })
+        .collect();
+
+    let temppath = path.with_extension("json.tmp");
+    fs::write(&temppath, serde_json::to_string_pretty(&saved)?)?;
+    fs::rename(&temppath, &path)?;
+
+    Ok(())
+}
+
+/// runs a function on every copy of a track in the library
+/// 
+/// # arguments
+/// * 'playlists' - the playlists to search
+/// * 'id' - id of the track
+/// * 'f' - function to run on each copy
+/// 
+/// # returns
+/// * none
+pub fn updatetrack(playlists: &mut [Playlist], id: &str, mut f: impl FnMut(&mut Track)) {
+    for playlist in playlists.iter_mut() {
+        for track in playlist.tracks.iter_mut().filter(|t| t.id == id) {
+            f(track);
+        }
+    }
+}
+
+/// runs a function on every copy of a track in the queue and the library
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'id' - id of the track
+/// * 'f' - function to run on each copy
+/// 
+/// # returns
+/// * none
+pub fn edittrack(app: &mut App, id: &str, mut f: impl FnMut(&mut Track)) {
+    for track in app.queue.iter_mut().filter(|t| t.id == id) {
+        f(track);
+    }
+    updatetrack(&mut app.playlists, id, f);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consts::SourceKind;
+    use crate::testing::fixture;
+
+    #[test]
+    fn anoldlibrarygetsidsanddefaults() {
+        // saved before tracks had ids, only title, artist, duration and url
+        let playlists = parse(&fixture("oldlibrary.json")).unwrap();
+        assert_eq!(playlists.len(), 1);
+        assert_eq!(playlists[0].name, "old playlist");
+        assert_eq!(playlists[0].kind, PlaylistKind::Tracks);
+
+        let tracks = &playlists[0].tracks;
+        assert_eq!(tracks[0].id, "dQw4w9WgXcQ");
+        assert_eq!(tracks[0].source, SourceKind::YouTube);
+        assert_eq!(tracks[1].id, "url:baa81b35e5e58311");
+        assert_eq!(tracks[1].source, SourceKind::Url);
+        for track in tracks {
+            assert_eq!((track.playcount, track.lastplayed, track.added, track.rating), (0, None, None, None));
+            assert!(track.chapters.is_empty() && track.bookmarks.is_empty());
+            assert_eq!((track.played, track.resumeat, track.download.as_deref()), (false, 0, None));
+        }
+        assert_eq!(tracks[0].title, "goodbye");
+        assert_eq!(tracks[1].duration, 240);
+
+        // saved again and loaded, the ids stay the same
+        let again = parse(&serde_json::to_string(&playlists).unwrap()).unwrap();
+        assert_eq!(again, playlists);
+        assert_eq!(again[0].tracks[1].id, tracks[1].id);
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use anyhow::Result;
+use log::{LevelFilter, Log, Metadata, Record};
+use crate::paths;
+
+static MAXLOGBYTES: u64 = 1024 * 1024;  // size at which the log is rotated
+static MAXLOGFILES: usize = 3;          // rotated logs kept, mpvlayer.log.1 to mpvlayer.log.3
+static MAXRECENTLINES: usize = 500;     // lines kept in memory for the log viewer
+static LOGENV: &str = "MPVLAYER_LOG";
+
+static LOGGER: OnceLock<FileLogger> = OnceLock::new();
+
+/// writes log records to $XDG_STATE_HOME/mpvlayer/mpvlayer.log, rotating it when it gets big
+struct FileLogger {
+    path: PathBuf,
+    file: Mutex<Option<(File, u64)>>,       // the open log and how many bytes it holds
+    recent: Mutex<VecDeque<String>>,        // last lines written, for the log viewer
+}
+
+impl FileLogger {
+    /// PRIVATE function that shifts mpvlayer.log to mpvlayer.log.1 and so on, dropping the oldest
+    fn rotate(&self) {
+        for idx in (1..MAXLOGFILES).rev() {
+            let _ = fs::rename(self.rotatedpath(idx), self.rotatedpath(idx + 1));
+        }
+        let _ = fs::rename(&self.path, self.rotatedpath(1));
+    }
+
+    fn rotatedpath(&self, idx: usize) -> PathBuf {
+        self.path.with_extension(format!("log.{}", idx))
+    }
+
+    fn open(&self) -> Option<(File, u64)> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path).ok()?;
+        let len = file.metadata().map(|m| m.len()).unwrap_or(0);
+        Some((file, len))
+    }
+}
+
+impl Log for FileLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let line = format!(
+            "{}.{:03} {:<5} {}: {}",
+            now.as_secs(),
+            now.subsec_millis(),
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        if let Ok(mut file) = self.file.lock() {
+            if file.as_ref().is_some_and(|(_, len)| *len >= MAXLOGBYTES) {
+                *file = None;
+                self.rotate();
+            }
+            if file.is_none() {
+                *file = self.open();
+            }
+            if let Some((file, len)) = file.as_mut() {
+                if writeln!(file, "{}", line).is_ok() {
+                    *len += line.len() as u64 + 1;
+                }
+            }
+        }
+
+        if let Ok(mut recent) = self.recent.lock() {
+            recent.push_back(line);
+            if recent.len() > MAXRECENTLINES {
+                recent.pop_front();
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some((file, _)) = file.as_mut() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+/// gets the log level from `--log-level <level>`, `--log-level=<level>` or $MPVLAYER_LOG
+/// 
+/// # arguments
+/// * none
+/// 
+/// # returns
+/// * the level, warn if none was given or it could not be parsed
+pub fn getlevel() -> LevelFilter {
+    let args: Vec<String> = env::args().collect();
+    let fromargs = args.iter().enumerate().find_map(|(idx, arg)| {
+        if let Some(level) = arg.strip_prefix("--log-level=") {
+            Some(level.to_string())
+        } else if arg == "--log-level" {
+            args.get(idx + 1).cloned()
+        } else {
+            None
+        }
+    });
+
+    fromargs
+        .or_else(|| env::var(LOGENV).ok())
+        .and_then(|level| LevelFilter::from_str(level.trim()).ok())
+        .unwrap_or(LevelFilter::Warn)
+}
+
+/// starts logging to the log file
+/// 
+/// # arguments
+/// * 'level' - the most verbose level to record
+/// 
+/// # returns
+/// * none
+pub fn init(level: LevelFilter) -> Result<()> {
+    let dir = paths::statedir();
+    fs::create_dir_all(&dir)?;
+
+    let logger = LOGGER.get_or_init(|| FileLogger {
+        path: dir.join("mpvlayer.log"),
+        file: Mutex::new(None),
+        recent: Mutex::new(VecDeque::new()),
+    });
+    log::set_logger(logger).map_err(|e| anyhow::anyhow!("failed to set logger: {}", e))?;
+    log::set_max_level(level);
+
+    Ok(())
+}
+
+/// gets the lines logged this session, newest first
+/// 
+/// # returns
+/// * the lines as a Vec<String>
+pub fn recentlines() -> Vec<String> {
+    let lines: Vec<String> = LOGGER
+        .get()
+        .and_then(|logger| logger.recent.lock().ok())
+        .map(|recent| recent.iter().rev().cloned().collect())
+        .unwrap_or_default();
+
+    if lines.is_empty() {
+        vec![format!("nothing logged at level {}", log::max_level())]
+    } else {
+        lines
+    }
+}
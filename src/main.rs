@@ -1,26 +1,49 @@
 use std::{
-    io, time::Duration
+    io, panic::{self, AssertUnwindSafe}, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration
 };
 use constructors::{construct, rendermainview};
-use consts::PopupState;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
-    backend::CrosstermBackend, widgets::ListState, Terminal
+    backend::CrosstermBackend, Terminal
 };
 use anyhow::Result;
-use fs4::fs_std::FileExt;
+use scopeguard::defer;
+use signal_hook::consts::{SIGCONT, SIGHUP, SIGINT, SIGTERM, SIGTSTP};
 use crate::consts::{
-    App, Playlist, Track, RepeatType, CurrentColumn, LOCKPATH
+    App, Playlist, Track
 };
 
+mod actions;
 mod app;
+mod art;
+mod audio;
 mod backend;
+mod bookmarks;
+mod chapters;
+mod config;
 mod constructors;
 mod consts;
+mod crossfade;
+mod error;
+mod feeds;
+mod instance;
+mod library;
+mod logging;
+mod maintenance;
+mod metadata;
+mod paths;
+mod player;
+mod sleep;
+mod smart;
+mod sponsorblock;
+mod stream;
+mod supervisor;
+#[cfg(test)]
+mod testing;
 
 
 fn draw(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) -> Result<()> {
@@ -31,126 +54,113 @@ fn draw(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App) ->
     Ok(())
 }
 
-fn main() -> Result<()> {
-    // --- setup terminal ---
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?; 
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-
-    // fake playlists for now
-    let surfacebyaerochord = Track { 
-        title: String::from("surface"),
-        artist: String::from("aerochord"),
-        duration: 255,
-        url: String::from("https://www.youtube.com/watch?v=3FPwcaflCS8")
-    };
-
-    let dumdeedum = Track {
-        title: String::from("dum dee dum"),
-        artist: String::from("keys n' krates"),
-        duration: 183,
-        url: String::from("https://www.youtube.com/watch?v=eDshx6Rg9Hs")
-    };
-
-    let traproyalty = Track {
-        title: String::from("trap royalty"),
-        artist: String::from("very cool tutorials"),
-        duration: 73,
-        url: String::from("https://www.youtube.com/watch?v=bzQdrvKAwR8")
-    };
-
-    let goodbye = Track {
-        title: String::from("goodbye"),
-        artist: String::from("irokz"),
-        duration: 240,
-        url: String::from("https://www.youtube.com/watch?v=jJxJ8O_fMgg")
-    };
-
-    let glockinmyrawri = Track {
-        title: String::from("glock in my rawri"),
-        artist: String::from("randy!"),
-        duration: 136,
-        url: String::from("https://www.youtube.com/watch?v=lWiRuvoOdGc")
-    };
-
-    let tspmo = Track {
-        title: String::from("tspmo"),
-        artist: String::from("tyla da creata"),
-        duration: 180,
-        url: String::from("https://www.youtube.com/watch?v=oLbrmJLlvgM")
-    };
+/// playlists shown before anything has been saved
+fn sampleplaylists() -> Vec<Playlist> {
+    let surfacebyaerochord = Track::new("surface", "aerochord", 255, "https://www.youtube.com/watch?v=3FPwcaflCS8");
+    let dumdeedum = Track::new("dum dee dum", "keys n' krates", 183, "https://www.youtube.com/watch?v=eDshx6Rg9Hs");
+    let traproyalty = Track::new("trap royalty", "very cool tutorials", 73, "https://www.youtube.com/watch?v=bzQdrvKAwR8");
+    let goodbye = Track::new("goodbye", "irokz", 240, "https://www.youtube.com/watch?v=jJxJ8O_fMgg");
+    let glockinmyrawri = Track::new("glock in my rawri", "randy!", 136, "https://www.youtube.com/watch?v=lWiRuvoOdGc");
+    let tspmo = Track::new("tspmo", "tyla da creata", 180, "https://www.youtube.com/watch?v=oLbrmJLlvgM");
 
     let sigmaplaylist = Playlist {
         name: String::from("sigma"),
-        tracks: vec![surfacebyaerochord.clone(), dumdeedum.clone(), tspmo.clone()]
+        tracks: vec![surfacebyaerochord.clone(), dumdeedum.clone(), tspmo.clone()],
+        ..Playlist::default()
     };
 
     let sigmaplaylistcopy = Playlist {
         name: String::from("sigma copy"),
-        tracks: vec![traproyalty.clone(), goodbye.clone(), glockinmyrawri.clone(), surfacebyaerochord.clone(), dumdeedum.clone(), tspmo.clone()]
+        tracks: vec![traproyalty, goodbye, glockinmyrawri, surfacebyaerochord, dumdeedum, tspmo],
+        ..Playlist::default()
     };
 
-    // --- initialize app ---
-    let mut app = App {
-        running: true,
-        playing: false,
-        version: String::from("0.0.1"),
-        repeatedinstance: false,
-        playlists: vec![sigmaplaylist.clone(), sigmaplaylistcopy.clone()],
-        queue: Vec::new(),
-        queuebeforeshuffle: None,
-        queuebeforerepeat: None,
-        currentqueueidx: 0,
-        currentplaylistidx: 0,
-        currentdurationsecs: 0,
-        shuffle: false,
-        repeat: RepeatType::None,
-        mpv: None,
-        currentcolumn: CurrentColumn::Playlists,
-        playliststate: ListState::default(),
-        tracksstate: ListState::default(),
-        queuestate: ListState::default(),
-        lockfile: None,
-        popup: PopupState {
-            onscreen: false,
-            dangerous: false,
-            title: String::from(""),
-            message: Vec::new()
-        }
-    };
+    vec![sigmaplaylist, sigmaplaylistcopy]
+}
+
+/// signals the main loop reacts to
+struct Signals {
+    terminate: Arc<AtomicBool>, // SIGINT, SIGTERM or SIGHUP
+    suspend: Arc<AtomicBool>,   // SIGTSTP
+    resumed: Arc<AtomicBool>,   // SIGCONT
+}
+
+/// puts the terminal into raw mode on the alternate screen
+fn setupterminal() -> Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    Ok(())
+}
+
+/// gives the terminal back to the shell, ignoring errors since this also runs while panicking
+fn restoreterminal() {
+    let _ = execute!(io::stdout(), LeaveAlternateScreen);
+    let _ = disable_raw_mode();
+}
 
-    app::firstrun(&mut app)?;
+/// suspends mpvlayer like ctrl-z would, restoring the terminal while stopped
+/// 
+/// mpv is in its own process group so the music keeps playing
+fn suspend(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+    restoreterminal();
+    // SIGSTOP can not be caught, execution continues here on SIGCONT
+    unsafe { libc::raise(libc::SIGSTOP) };
+    setupterminal()?;
+    terminal.clear()?;
+    Ok(())
+}
+
+/// runs the main loop until the app quits or a signal asks it to
+fn run(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut App, signals: &Signals) -> Result<()> {
     let mut counter: u8 = 0;
 
     // --- draw announcement popups, etc etc ---
-    terminal.draw(|frame| {
-        // constructors::showpopup(&mut app, frame, String::from("hello"), vec![String::from("world")], false);
+    terminal.draw(|_frame| {
+        // constructors::showpopup(&mut app, String::from("hello"), vec![String::from("world")], false);
     })?;
 
     // --- main loop ---
     while app.running {
-        draw(&mut terminal, &mut app)?;
+        if signals.terminate.load(Ordering::Relaxed) {
+            break;
+        }
+        if signals.suspend.swap(false, Ordering::Relaxed) {
+            suspend(terminal)?;
+            signals.resumed.store(false, Ordering::Relaxed);
+        }
+        if signals.resumed.swap(false, Ordering::Relaxed) {
+            // continued after someone else stopped us, the terminal may have been reset
+            setupterminal()?;
+            terminal.clear()?;
+        }
+
+        draw(terminal, app)?;
 
         // --- event Handling ---
         if event::poll(Duration::from_millis(250))? {
             if let Event::Key(key) = event::read()? {
                 if key.kind == KeyEventKind::Press {
-                    let keycode = key.code;
-                    if app.repeatedinstance {
-                        if key.code == KeyCode::Enter {
-                            app.running = false;
-                        }
+                    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+                    if ctrl && key.code == KeyCode::Char('c') {
+                        // raw mode turns ctrl-c into a key instead of SIGINT
+                        app.running = false;
+                    } else if ctrl && key.code == KeyCode::Char('z') {
+                        // same for ctrl-z and SIGTSTP
+                        suspend(terminal)?;
                     } else {
-                        app::onkey(&mut app, keycode)?;
+                        // repeated instances are handled in onkey too
+                        if let Err(e) = app::onkey(app, key.code) {
+                            app::reporterror(app, e);
+                        }
                     }
                 }
             }
         }
 
         if !app.repeatedinstance {
-            app::ontick(&mut app, &counter)?;
+            if let Err(e) = app::ontick(app, &counter) {
+                app::reporterror(app, e);
+            }
             if counter >= 3 {
                 counter = 0;
             } else {
@@ -162,22 +172,114 @@ fn main() -> Result<()> {
         }
     }
 
-    // --- manual cleanup ---
-    // unlock and remove lock file (only if this instance held the lock)
+    Ok(())
+}
+
+/// cleans up after the app, whether it quit, returned an error or panicked
+/// 
+/// every step runs even if an earlier one fails, the first error is returned
+fn teardown(app: &mut App) -> Result<()> {
+    log::info!("tearing down");
+    let mut result = Ok(());
+
+    // save the library before anything else can go wrong (only if this instance owns it)
+    if !app.repeatedinstance {
+        result = library::save(&app.playlists);
+        let _ = metadata::savecache(&app.metadata);
+        let _ = sponsorblock::savecache(&app.sponsorblock);
+    }
+
+    // unlock, the lock file stays so the next instance locks the same file
     if let Some(file) = app.lockfile.take() {
         let _ = file.unlock();
-        let _ = std::fs::remove_file(LOCKPATH);
     }
 
-    // kill mpv process 
-    if let Some(mut child) = app.mpv.take() {
-        let _ = child.kill();
-        let _ = child.wait(); // wait for the process to actually exit
+    // stop listening for other instances
+    if let Some(control) = app.control.take() {
+        let _ = std::fs::remove_file(&control.path);
     }
-    
-    // restore terminal
-    execute!(io::stdout(), LeaveAlternateScreen)?;
-    disable_raw_mode()?;
 
-    Ok(())
+    // kill mpv process, and the one fading out if a crossfade is going on
+    crossfade::cut(app);
+    app.player.stop(); // waits for the process to actually exit
+
+    restoreterminal();
+    result
+}
+
+fn main() -> Result<()> {
+    // --- log to a file, the alternate screen swallows everything else ---
+    let _ = logging::init(logging::getlevel());
+    log::info!("mpvlayer starting, pid {}", std::process::id());
+
+    // --- restore the terminal before the panic message is printed, or it is lost ---
+    let defaulthook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        player::killmpvgroup();
+        restoreterminal();
+        log::error!("panic: {}", info);
+        defaulthook(info);
+    }));
+
+    // --- setup terminal ---
+    setupterminal()?;
+
+    // --- restore the terminal and kill our mpv if setup fails before the app exists ---
+    defer! {
+        player::killmpvgroup();
+        restoreterminal();
+    }
+
+    // --- quit cleanly on SIGINT, SIGTERM and SIGHUP, suspend cleanly on SIGTSTP ---
+    let signals = Signals {
+        terminate: Arc::new(AtomicBool::new(false)),
+        suspend: Arc::new(AtomicBool::new(false)),
+        resumed: Arc::new(AtomicBool::new(false)),
+    };
+    for signal in [SIGINT, SIGTERM, SIGHUP] {
+        signal_hook::flag::register(signal, Arc::clone(&signals.terminate))?;
+    }
+    signal_hook::flag::register(SIGTSTP, Arc::clone(&signals.suspend))?;
+    signal_hook::flag::register(SIGCONT, Arc::clone(&signals.resumed))?;
+
+    let backend = CrosstermBackend::new(io::stdout());
+    let mut terminal = Terminal::new(backend)?;
+    let picker = art::getpicker();
+
+    // --- load the library, with some sample playlists on first run ---
+    let playlists = match library::load()? {
+        Some(playlists) => playlists,
+        None => sampleplaylists(),
+    };
+
+    // --- initialize app ---
+    // --- a broken config should not stop the music, fall back to the defaults ---
+    let (config, configerror) = match config::load() {
+        Ok(config) => (config, None),
+        Err(e) => (config::Config::default(), Some(e)),
+    };
+
+    let player = player::MpvPlayer::new(paths::mpvsocket()?, config::mpvargs(&config));
+    let mut app = App::new(playlists, config, Box::new(player), picker);
+    if let Some(e) = configerror {
+        app::reporterror(&mut app, e);
+    }
+    if let Err(e) = audio::apply(&mut app) {
+        app::reporterror(&mut app, e);
+    }
+
+    // --- run, then always tear down the same way ---
+    let result = panic::catch_unwind(AssertUnwindSafe(|| -> Result<()> {
+        app::firstrun(&mut app)?;
+        if !app.repeatedinstance {
+            metadata::requestmissing(&mut app);
+        }
+        run(&mut terminal, &mut app, &signals)
+    }));
+    let teardownresult = teardown(&mut app);
+
+    match result {
+        Ok(result) => result.and(teardownresult),
+        Err(payload) => panic::resume_unwind(payload),
+    }
 }
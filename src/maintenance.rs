@@ -0,0 +1,392 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use crate::consts::{App, Playlist, PlaylistKind, SourceKind, StatusLevel, Track};
+
+/// whether a track's url still plays
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum LinkState {
+    Ok,
+    Private,            // a private video
+    Removed,            // taken down, deleted or otherwise unavailable
+    Missing,            // a file that is not on disk anymore
+    Failed(String),     // could not be checked, e.g. no network, so it is not counted as dead
+}
+
+impl LinkState {
+    /// checks if the track will never play again and can be removed
+    pub fn isdead(&self) -> bool {
+        matches!(self, LinkState::Private | LinkState::Removed | LinkState::Missing)
+    }
+
+    /// gets a short description for the maintenance view
+    pub fn describe(&self) -> &str {
+        match self {
+            LinkState::Ok => "ok",
+            LinkState::Private => "private",
+            LinkState::Removed => "removed",
+            LinkState::Missing => "missing",
+            LinkState::Failed(_) => "unknown",
+        }
+    }
+}
+
+/// checks links on a background thread, one yt-dlp at a time
+#[derive(Debug)]
+pub struct LinkChecker {
+    states: HashMap<String, LinkState>,             // what each url was found to be
+    requests: Sender<String>,                       // urls for the worker to check
+    results: Receiver<(String, LinkState)>,         // what the worker found
+    inflight: HashSet<String>,                      // urls sent to the worker but not back yet
+}
+
+impl LinkChecker {
+    /// creates a checker and starts its worker thread
+    pub fn new() -> Self {
+        let (requests, workerrequests) = mpsc::channel::<String>();
+        let (workerresults, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            for url in workerrequests {
+                let state = checkremote(&url);
+                if workerresults.send((url, state)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        LinkChecker {
+            states: HashMap::new(),
+            requests,
+            results,
+            inflight: HashSet::new(),
+        }
+    }
+}
+
+/// tracks that are the same song, as positions in the library
+pub type DuplicateGroup = Vec<(usize, usize)>;  // (playlist index, track index)
+
+/// PRIVATE function that gets the playlists maintenance works on, smart playlists only hold copies
+fn editable(playlist: &Playlist) -> bool {
+    playlist.kind != PlaylistKind::Smart
+}
+
+/// normalises a title or artist for comparison, ignoring case and punctuation
+pub fn normalise(text: &str) -> String {
+    text.to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// PRIVATE function that gets what makes two tracks the same: their id, and their title and artist if known
+fn keys(track: &Track) -> Vec<String> {
+    let mut keys = vec![track.id.clone()];
+    let (title, artist) = (normalise(&track.title), normalise(&track.artist));
+    if !title.is_empty() && !artist.is_empty() {
+        keys.push(format!("meta:{}\n{}", title, artist));
+    }
+    keys
+}
+
+/// finds tracks that appear more than once, within a playlist or across playlists
+///
+/// tracks are the same if they have the same id (the video id for youtube), or the same
+/// title and artist once case and punctuation are ignored
+///
+/// # arguments
+/// * 'playlists' - the library
+///
+/// # returns
+/// * groups of tracks that are the same, in library order
+pub fn duplicates(playlists: &[Playlist]) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    let mut groupbykey: HashMap<String, usize> = HashMap::new();
+
+    for (playlistidx, playlist) in playlists.iter().enumerate().filter(|(_, p)| editable(p)) {
+        for (trackidx, track) in playlist.tracks.iter().enumerate() {
+            let keys = keys(track);
+            let groupidx = match keys.iter().find_map(|key| groupbykey.get(key)) {
+                Some(groupidx) => *groupidx,
+                None => {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                }
+            };
+            groups[groupidx].push((playlistidx, trackidx));
+            for key in keys {
+                groupbykey.entry(key).or_insert(groupidx);
+            }
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// removes duplicates, keeping the first of each in library order
+///
+/// by default only duplicates within a playlist go, the same track in different playlists is
+/// usually on purpose
+///
+/// # arguments
+/// * 'playlists' - the library
+/// * 'acrossplaylists' - also remove copies of tracks that are in an earlier playlist
+///
+/// # returns
+/// * how many tracks were removed
+pub fn dedupe(playlists: &mut [Playlist], acrossplaylists: bool) -> usize {
+    let mut removed = 0;
+    let mut seen: HashSet<String> = HashSet::new();
+    for playlist in playlists.iter_mut().filter(|p| editable(p)) {
+        if !acrossplaylists {
+            seen.clear();
+        }
+        let before = playlist.tracks.len();
+        playlist.tracks.retain(|track| {
+            let keys = keys(track);
+            let duplicate = keys.iter().any(|key| seen.contains(key));
+            seen.extend(keys);
+            !duplicate
+        });
+        removed += before - playlist.tracks.len();
+    }
+    removed
+}
+
+/// messages yt-dlp prints for videos that are gone for good
+static REMOVEDREASONS: [&str; 6] = [
+    "video unavailable. this video has been removed",
+    "this video has been removed by the uploader",
+    "this video is no longer available because the youtube account associated with this video has been terminated",
+    "this video is no longer available due to a copyright claim",
+    "this video has been removed for violating",
+    "http error 404",
+];
+
+/// works out why yt-dlp could not play a url from what it printed
+///
+/// only messages that mean the video is gone for good count as removed, anything else like a
+/// geo block or a missing format might play later and is left as failed
+///
+/// # arguments
+/// * 'stderr' - what yt-dlp printed
+///
+/// # returns
+/// * the state of the link
+pub fn classify(stderr: &str) -> LinkState {
+    let stderr = stderr.to_lowercase();
+    if stderr.contains("private video") {
+        LinkState::Private
+    } else if REMOVEDREASONS.iter().any(|reason| stderr.contains(reason))
+        || stderr.lines().any(|l| l.trim_end().ends_with("video unavailable"))
+    {
+        LinkState::Removed
+    } else {
+        let reason = stderr.lines().rev().find(|l| l.contains("error")).unwrap_or("").trim().to_string();
+        LinkState::Failed(reason)
+    }
+}
+
+/// PRIVATE function that asks yt-dlp if a url still plays
+fn checkremote(url: &str) -> LinkState {
+    log::debug!("checking {}", url);
+    let output = Command::new("yt-dlp")
+        .args(["--simulate", "--quiet", "--no-warnings", "--no-playlist"])
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => LinkState::Ok,
+        Ok(output) => classify(&String::from_utf8_lossy(&output.stderr)),
+        Err(e) => LinkState::Failed(format!("could not run yt-dlp: {}", e)),
+    }
+}
+
+/// checks every url in the library, files straight away and everything else with yt-dlp
+///
+/// streams and podcast episodes are not checked, yt-dlp does not know about most of them
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn checklinks(app: &mut App) {
+    let tracks: Vec<(String, SourceKind)> = app.playlists.iter()
+        .filter(|p| editable(p))
+        .flat_map(|p| p.tracks.iter())
+        .filter(|t| !t.islive() && !t.isepisode())
+        .map(|t| (t.url.clone(), t.source))
+        .collect();
+
+    let checker = &mut app.links;
+    checker.states.clear();
+    for (url, source) in tracks {
+        if checker.states.contains_key(&url) || checker.inflight.contains(&url) {
+            continue;
+        }
+        if source == SourceKind::File || url.starts_with('/') {
+            let state = if Path::new(&url).exists() { LinkState::Ok } else { LinkState::Missing };
+            checker.states.insert(url, state);
+        } else if checker.requests.send(url.clone()).is_ok() {
+            checker.inflight.insert(url);
+        }
+    }
+    app.status.push(StatusLevel::Info, String::from("checking every link in the library"));
+    refreshreport(app);
+}
+
+/// stores finished checks
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn update(app: &mut App) {
+    let mut changed = false;
+    while let Ok((url, state)) = app.links.results.try_recv() {
+        changed = true;
+        app.links.inflight.remove(&url);
+        if state.isdead() {
+            log::info!("{} is {}", url, state.describe());
+        }
+        if let LinkState::Failed(reason) = &state {
+            log::warn!("could not check {}: {}", url, reason);
+        }
+        app.links.states.insert(url, state);
+    }
+    if changed && app.ui.maintenance.onscreen {
+        refreshreport(app);
+    }
+}
+
+/// removes every track whose link was found dead
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * how many tracks were removed
+pub fn removedead(app: &mut App) -> usize {
+    let states = &app.links.states;
+    let mut removed = 0;
+    for playlist in app.playlists.iter_mut().filter(|p| editable(p)) {
+        let before = playlist.tracks.len();
+        playlist.tracks.retain(|t| !states.get(&t.url).is_some_and(|s| s.isdead()));
+        removed += before - playlist.tracks.len();
+    }
+    removed
+}
+
+/// works out the lines of the maintenance view again, after the library or a link check changed
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn refreshreport(app: &mut App) {
+    app.ui.maintenance.report = report(app);
+}
+
+/// builds the lines of the maintenance view
+///
+/// # arguments
+/// * 'app' - reference to the app state
+///
+/// # returns
+/// * the lines
+pub fn report(app: &App) -> Vec<String> {
+    let mut lines = vec![String::from("── duplicates ──")];
+    let groups = duplicates(&app.playlists);
+    if groups.is_empty() {
+        lines.push(String::from("no duplicates"));
+    }
+    for group in &groups {
+        let (playlistidx, trackidx) = group[0];
+        let track = &app.playlists[playlistidx].tracks[trackidx];
+        lines.push(format!("{} - {}", track.displaytitle(), track.artist));
+        let places: Vec<String> = group.iter()
+            .map(|(playlistidx, trackidx)| format!("{} #{}", app.playlists[*playlistidx].name, trackidx + 1))
+            .collect();
+        lines.push(format!("  {}", places.join(", ")));
+    }
+
+    lines.push(String::new());
+    lines.push(String::from("── links ──"));
+    let checker = &app.links;
+    if checker.states.is_empty() && checker.inflight.is_empty() {
+        lines.push(String::from("not checked yet, press c to check them"));
+        return lines;
+    }
+
+    let dead = checker.states.values().filter(|s| s.isdead()).count();
+    let unknown = checker.states.values().filter(|s| matches!(s, LinkState::Failed(_))).count();
+    let total = checker.states.len() + checker.inflight.len();
+    lines.push(format!("checked {} of {}, {} dead, {} could not be checked", checker.states.len(), total, dead, unknown));
+    for playlist in app.playlists.iter().filter(|p| editable(p)) {
+        for track in &playlist.tracks {
+            if let Some(state) = checker.states.get(&track.url).filter(|s| s.isdead()) {
+                lines.push(format!("  {:<8} {} ({})", state.describe(), track.displaytitle(), playlist.name));
+            }
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::testapp;
+
+    #[test]
+    fn duplicatesarefoundbyidandbytitle() {
+        let mut app = testapp(3);
+        let mut reupload = Track::new("Track 0!", "Artist", 100, "https://example.com/reupload");
+        reupload.added = None;
+        app.playlists[0].tracks.push(reupload);
+        let copy = app.playlists[0].tracks[1].clone();
+        app.playlists.push(Playlist { name: String::from("other"), tracks: vec![copy], ..Playlist::default() });
+
+        let groups = duplicates(&app.playlists);
+        assert_eq!(groups, [vec![(0, 0), (0, 3)], vec![(0, 1), (1, 0)]]);
+
+        // only duplicates within a playlist are removed
+        assert_eq!(dedupe(&mut app.playlists, false), 1);
+        assert_eq!(app.playlists[0].tracks.len(), 3);
+        assert_eq!(app.playlists[1].tracks.len(), 1);
+
+        // across the library the copy in the later playlist goes too
+        assert_eq!(dedupe(&mut app.playlists, true), 1);
+        assert_eq!(app.playlists[0].tracks.len(), 3);
+        assert!(app.playlists[1].tracks.is_empty());
+    }
+
+    #[test]
+    fn deadlinksareclassifiedandremoved() {
+        assert_eq!(classify("ERROR: [youtube] abc: Private video. Sign in if you've been granted access"), LinkState::Private);
+        assert_eq!(classify("ERROR: [youtube] abc: Video unavailable. This video has been removed by the uploader"), LinkState::Removed);
+        assert!(!classify("ERROR: Unable to download webpage: <urlopen error [Errno -3]>").isdead());
+        assert!(!classify("ERROR: [youtube] abc: Video unavailable. The uploader has not made this video available in your country").isdead());
+        assert!(!classify("ERROR: [youtube] abc: Requested format is not available").isdead());
+        assert_eq!(classify("ERROR: [youtube] abc: Video unavailable"), LinkState::Removed);
+
+        let mut app = testapp(0);
+        let missing = Track::new("gone", "artist", 100, "/nonexistent/mpvlayer-test.mp3");
+        let present = Track::new("here", "artist", 100, env!("CARGO_MANIFEST_DIR"));
+        app.playlists[0].tracks = vec![missing, present];
+        checklinks(&mut app);
+        assert_eq!(removedead(&mut app), 1);
+        assert_eq!(app.playlists[0].tracks[0].title, "here");
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::chapters;
+use crate::consts::{App, Chapter, Playlist, SourceKind, Track};
+use crate::paths;
+use crate::smart;
+
+/// metadata learned about a url
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrackMeta {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<u32>,
+    pub thumbnail: Option<String>,
+    pub chapters: Vec<Chapter>,
+    pub live: bool,         // a live stream, which has no duration
+}
+
+/// resolves missing track metadata on a background thread
+/// 
+/// urls are resolved one at a time so a big library does not spawn hundreds of yt-dlps
+#[derive(Debug)]
+pub struct Resolver {
+    cache: HashMap<String, TrackMeta>,              // resolved metadata by url
+    requests: Sender<String>,                       // urls for the worker to resolve
+    results: Receiver<(String, Option<TrackMeta>)>, // what the worker found
+    inflight: HashSet<String>,                      // urls sent to the worker but not back yet
+    failed: HashSet<String>,                        // urls that could not be resolved this session
+}
+
+impl Resolver {
+    /// creates a resolver with the on disk cache loaded and starts its worker thread
+    pub fn new() -> Self {
+        let (requests, workerrequests) = mpsc::channel::<String>();
+        let (workerresults, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            for url in workerrequests {
+                let meta = resolve(&url).ok();
+                if workerresults.send((url, meta)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Resolver {
+            cache: loadcache(),
+            requests,
+            results,
+            inflight: HashSet::new(),
+            failed: HashSet::new(),
+        }
+    }
+}
+
+/// gets the path the metadata cache is saved at
+pub fn cachepath() -> PathBuf {
+    paths::cachedir().join("metadata.json")
+}
+
+/// PRIVATE function that loads the metadata cache, starting empty if it is missing or broken
+fn loadcache() -> HashMap<String, TrackMeta> {
+    fs::read_to_string(cachepath())
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// saves the metadata cache
+/// 
+/// # arguments
+/// * 'resolver' - the resolver whose cache to save
+/// 
+/// # returns
+/// * none
+pub fn savecache(resolver: &Resolver) -> Result<()> {
+    let path = cachepath();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(&resolver.cache)?)?;
+    Ok(())
+}
+
+/// checks if a track is missing metadata worth looking up
+pub fn ismissing(track: &Track) -> bool {
+    track.title.is_empty() || track.artist.is_empty() || (track.duration == 0 && !track.islive())
+}
+
+/// resolves the metadata of a url
+/// 
+/// local files are read with ffprobe, anything else with yt-dlp
+/// 
+/// # arguments
+/// * 'url' - url or path of the track
+/// 
+/// # returns
+/// * the metadata that was found
+pub fn resolve(url: &str) -> Result<TrackMeta> {
+    if Path::new(url).exists() {
+        resolvelocal(url)
+    } else {
+        resolveremote(url)
+    }
+}
+
+/// PRIVATE function that runs a command and parses its stdout as json
+fn runjson(command: &mut Command) -> Result<Value> {
+    log::debug!("running {:?}", command);
+    let output = command
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+
+    log::debug!("{:?} exited with {}", command.get_program(), output.status);
+    if !output.status.success() {
+        return Err(anyhow::anyhow!("{:?} exited with {}", command.get_program(), output.status));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// PRIVATE function that gets a non empty string field from json
+fn getstring(json: &Value, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|key| json.get(key).and_then(|v| v.as_str()))
+        .find(|s| !s.is_empty())
+        .map(String::from)
+}
+
+/// resolves a remote url with `yt-dlp -J`
+pub fn resolveremote(url: &str) -> Result<TrackMeta> {
+    let json = runjson(
+        Command::new("yt-dlp")
+            .args(["-J", "--skip-download", "--no-playlist", "--no-warnings"])
+            .arg(url)
+    )?;
+
+    Ok(TrackMeta {
+        title: getstring(&json, &["track", "title"]),
+        artist: getstring(&json, &["artist", "creator", "uploader", "channel"]),
+        album: getstring(&json, &["album"]),
+        duration: json.get("duration").and_then(|d| d.as_f64()).map(|d| d.round() as u32),
+        thumbnail: getstring(&json, &["thumbnail"]),
+        chapters: json.get("chapters").map(chapters::parsechapters).unwrap_or_default(),
+        live: json.get("is_live").and_then(|l| l.as_bool()).unwrap_or(false),
+    })
+}
+
+/// resolves a local file with ffprobe
+pub fn resolvelocal(path: &str) -> Result<TrackMeta> {
+    let json = runjson(
+        Command::new("ffprobe")
+            .args(["-v", "quiet", "-print_format", "json", "-show_format", "-show_chapters"])
+            .arg(path)
+    )?;
+
+    let format = json.get("format").cloned().unwrap_or(Value::Null);
+    // tag names are not consistently cased between containers
+    let tags: Value = format.get("tags")
+        .and_then(|t| t.as_object())
+        .map(|t| t.iter().map(|(k, v)| (k.to_lowercase(), v.clone())).collect())
+        .unwrap_or(Value::Null);
+
+    let title = getstring(&tags, &["title"]).or_else(|| {
+        Path::new(path).file_stem().map(|stem| stem.to_string_lossy().into_owned())
+    });
+
+    Ok(TrackMeta {
+        title,
+        artist: getstring(&tags, &["artist", "album_artist"]),
+        album: getstring(&tags, &["album"]),
+        duration: format.get("duration")
+            .and_then(|d| d.as_str())
+            .and_then(|d| d.parse::<f64>().ok())
+            .map(|d| d.round() as u32),
+        thumbnail: None,
+        chapters: json.get("chapters").map(chapters::parsechapters).unwrap_or_default(),
+        live: false,
+    })
+}
+
+/// fills in the missing fields of a track, never overwriting what is already there
+/// 
+/// # arguments
+/// * 'track' - the track to fill in
+/// * 'meta' - the metadata to fill it with
+/// 
+/// # returns
+/// * none
+pub fn apply(track: &mut Track, meta: &TrackMeta) {
+    if track.title.is_empty() {
+        if let Some(title) = &meta.title {
+            track.title = title.clone();
+        }
+    }
+    if track.artist.is_empty() {
+        if let Some(artist) = &meta.artist {
+            track.artist = artist.clone();
+        }
+    }
+    if track.album.is_none() {
+        track.album = meta.album.clone();
+    }
+    if meta.live {
+        track.source = SourceKind::Stream;
+    }
+    if track.duration == 0 && !track.islive() {
+        track.duration = meta.duration.unwrap_or(0);
+    }
+    if track.thumbnail.is_none() {
+        track.thumbnail = meta.thumbnail.clone();
+    }
+    if track.chapters.is_empty() {
+        track.chapters = meta.chapters.clone();
+    }
+}
+
+/// PRIVATE function that applies metadata to every track with a url in the library and the queue
+fn applyeverywhere(playlists: &mut [Playlist], queue: &mut [Track], url: &str, meta: &TrackMeta) {
+    for playlist in playlists.iter_mut() {
+        for track in playlist.tracks.iter_mut().filter(|t| t.url == url) {
+            apply(track, meta);
+        }
+    }
+    for track in queue.iter_mut().filter(|t| t.url == url) {
+        apply(track, meta);
+    }
+}
+
+/// asks the resolver to look up a track if it is missing metadata
+/// 
+/// cached metadata is applied straight away
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'url' - url of the track
+/// 
+/// # returns
+/// * none
+pub fn request(app: &mut App, url: &str) {
+    if let Some(meta) = app.metadata.cache.get(url).cloned() {
+        applyeverywhere(&mut app.playlists, &mut app.queue, url, &meta);
+        smart::refresh(app);
+        return;
+    }
+
+    let resolver = &mut app.metadata;
+    if resolver.inflight.contains(url) || resolver.failed.contains(url) {
+        return;
+    }
+    if resolver.requests.send(String::from(url)).is_ok() {
+        resolver.inflight.insert(String::from(url));
+    }
+}
+
+/// requests metadata for every track in the library that is missing some
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn requestmissing(app: &mut App) {
+    // the same url can be in several playlists, far apart
+    let urls: HashSet<String> = app.playlists
+        .iter()
+        .flat_map(|p| p.tracks.iter())
+        .filter(|t| ismissing(t))
+        .map(|t| t.url.clone())
+        .collect();
+
+    for url in urls {
+        request(app, &url);
+    }
+}
+
+/// writes finished lookups back into the library and the queue
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn update(app: &mut App) {
+    let mut changed = false;
+    while let Ok((url, meta)) = app.metadata.results.try_recv() {
+        app.metadata.inflight.remove(&url);
+        match meta {
+            Some(meta) => {
+                applyeverywhere(&mut app.playlists, &mut app.queue, &url, &meta);
+                app.metadata.cache.insert(url, meta);
+                changed = true;
+            }
+            None => {
+                log::warn!("could not resolve metadata for {}", url);
+                app.metadata.failed.insert(url);
+            }
+        }
+    }
+    // the details smart playlists match on may have changed
+    if changed {
+        smart::refresh(app);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::testapp;
+
+    fn meta() -> TrackMeta {
+        TrackMeta {
+            title: Some(String::from("found title")),
+            artist: Some(String::from("found artist")),
+            album: Some(String::from("found album")),
+            duration: Some(240),
+            thumbnail: Some(String::from("https://example.com/thumb.jpg")),
+            chapters: vec![Chapter { title: String::from("intro"), start: 0 }],
+            live: false,
+        }
+    }
+
+    #[test]
+    fn applyfillsinonlythemissingfields() {
+        let mut track = Track::new("", "kept artist", 0, "https://example.com/a");
+        apply(&mut track, &meta());
+        assert_eq!(track.title, "found title");
+        assert_eq!(track.artist, "kept artist");
+        assert_eq!(track.album.as_deref(), Some("found album"));
+        assert_eq!(track.duration, 240);
+        assert_eq!(track.thumbnail.as_deref(), Some("https://example.com/thumb.jpg"));
+        assert_eq!(track.chapters.len(), 1);
+        assert!(!ismissing(&track));
+
+        // a known duration is not replaced
+        let mut track = Track::new("title", "artist", 100, "https://example.com/a");
+        apply(&mut track, &meta());
+        assert_eq!(track.title, "title");
+        assert_eq!(track.duration, 100);
+    }
+
+    #[test]
+    fn livestreamskeepnoduration() {
+        let mut track = Track::new("", "", 0, "https://example.com/live");
+        apply(&mut track, &TrackMeta { live: true, duration: Some(5), ..meta() });
+        assert!(track.islive());
+        assert_eq!(track.duration, 0);
+        assert!(!ismissing(&track));
+    }
+
+    #[test]
+    fn applyeverywherereachesthelibraryandthequeue() {
+        let mut app = testapp(2);
+        app.playlists[0].tracks[0].title.clear();
+        let copy = app.playlists[0].tracks[0].clone();
+        app.playlists.push(Playlist { name: String::from("other"), tracks: vec![copy.clone()], ..Playlist::default() });
+        app.queue = vec![copy, app.playlists[0].tracks[1].clone()];
+
+        applyeverywhere(&mut app.playlists, &mut app.queue, "https://example.com/0", &meta());
+        assert_eq!(app.playlists[0].tracks[0].title, "found title");
+        assert_eq!(app.playlists[1].tracks[0].title, "found title");
+        assert_eq!(app.queue[0].title, "found title");
+        // other urls are left alone
+        assert_eq!(app.playlists[0].tracks[1].title, "track 1");
+        assert_eq!(app.queue[1].album, None);
+    }
+}
@@ -0,0 +1,90 @@
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::PathBuf;
+use anyhow::Result;
+
+/// gets a base directory from an xdg variable, falling back to a path under $HOME
+/// 
+/// # arguments
+/// * 'var' - name of the xdg environment variable
+/// * 'fallback' - path relative to $HOME to use when the variable is unset
+/// 
+/// # returns
+/// * the mpvlayer subdirectory of the base directory
+fn xdgdir(var: &str, fallback: &str) -> PathBuf {
+    let base = match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(fallback),
+    };
+    base.join("mpvlayer")
+}
+
+/// gets the cache directory ($XDG_CACHE_HOME/mpvlayer)
+pub fn cachedir() -> PathBuf {
+    xdgdir("XDG_CACHE_HOME", ".cache")
+}
+
+/// gets the config directory ($XDG_CONFIG_HOME/mpvlayer)
+pub fn configdir() -> PathBuf {
+    xdgdir("XDG_CONFIG_HOME", ".config")
+}
+
+/// gets the data directory ($XDG_DATA_HOME/mpvlayer)
+pub fn datadir() -> PathBuf {
+    xdgdir("XDG_DATA_HOME", ".local/share")
+}
+
+/// gets the runtime directory for sockets and the lock file, creating it if needed
+/// 
+/// uses $XDG_RUNTIME_DIR/mpvlayer, or /tmp/mpvlayer-<uid> when that is unset.
+/// the directory is only accessible by the current user so nobody else can
+/// take over the mpv socket
+/// 
+/// # arguments
+/// * none
+/// 
+/// # returns
+/// * the path of the directory
+pub fn runtimedir() -> Result<PathBuf> {
+    let uid = unsafe { libc::getuid() };
+    let dir = match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("mpvlayer"),
+        _ => env::temp_dir().join(format!("mpvlayer-{}", uid)),
+    };
+
+    match fs::symlink_metadata(&dir) {
+        Ok(metadata) => {
+            // someone else could have created it in /tmp first
+            if !metadata.is_dir() || metadata.uid() != uid {
+                return Err(anyhow::anyhow!("'{}' is not a directory owned by you", dir.display()));
+            }
+            if metadata.permissions().mode() & 0o777 != 0o700 {
+                fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))?;
+            }
+        }
+        Err(_) => DirBuilder::new().recursive(true).mode(0o700).create(&dir)?,
+    }
+
+    Ok(dir)
+}
+
+/// gets the path of the single instance lock file
+pub fn lockpath() -> Result<PathBuf> {
+    Ok(runtimedir()?.join("mpvlayer.lock"))
+}
+
+/// gets the path of mpv's ipc socket
+pub fn mpvsocket() -> Result<PathBuf> {
+    Ok(runtimedir()?.join("mpvsocket"))
+}
+
+/// gets the path of the socket other instances send commands to
+pub fn controlsocket() -> Result<PathBuf> {
+    Ok(runtimedir()?.join("control.sock"))
+}
+
+/// gets the state directory ($XDG_STATE_HOME/mpvlayer)
+pub fn statedir() -> PathBuf {
+    xdgdir("XDG_STATE_HOME", ".local/state")
+}
@@ -0,0 +1,511 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::PathBuf;
+use std::process::{Child, ChildStderr, Command, Stdio};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use serde_json::Value;
+use crate::error::{BackendError, BackendResult};
+
+static IPCTIMEOUT: Duration = Duration::from_secs(2);
+static MAXSTDERRLINES: usize = 20;      // lines of mpv's stderr kept for the crash popup
+
+/// how a player stopped without being asked to
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum PlayerExit {
+    Finished,   // exited with status 0, which with keep-open is not the end of the track
+    Crashed     // anything else
+}
+
+/// something that plays one track at a time
+///
+/// commands use mpv's json ipc format, so anything mpv can do is reachable
+/// without the app knowing which player it is talking to
+pub trait Player: Debug {
+    /// starts playing a url, replacing whatever was playing
+    ///
+    /// # arguments
+    /// * 'url' - url or path of the track
+    /// * 'start' - position to start at in seconds
+    /// * 'paused' - whether to start paused
+    ///
+    /// # returns
+    /// * none
+    fn play(&mut self, url: &str, start: u32, paused: bool) -> BackendResult<()>;
+
+    /// stops playing and waits for the player to go away
+    fn stop(&mut self);
+
+    /// checks whether the player is still there
+    fn isrunning(&self) -> bool;
+
+    /// checks whether the player stopped on its own since the last call
+    ///
+    /// # returns
+    /// * how it stopped, None if it is still running or was stopped on purpose
+    fn poll(&mut self) -> Option<PlayerExit>;
+
+    /// gets the last errors the player reported, oldest first
+    fn errors(&self) -> Vec<String>;
+
+    /// sends a command and waits for the reply
+    ///
+    /// # arguments
+    /// * 'command' - the command and its arguments, e.g. ["get_property", "time-pos"]
+    ///
+    /// # returns
+    /// * the data replied with, Null for commands that do not return anything
+    fn command(&mut self, command: Value) -> BackendResult<Value>;
+
+    /// gets a property
+    ///
+    /// # arguments
+    /// * 'property' - name of the property
+    ///
+    /// # returns
+    /// * the value of the property
+    fn getproperty(&mut self, property: &str) -> BackendResult<Value> {
+        self.command(serde_json::json!(["get_property", property]))
+    }
+
+    /// sets a property
+    ///
+    /// # arguments
+    /// * 'property' - name of the property
+    /// * 'value' - the new value
+    ///
+    /// # returns
+    /// * none
+    fn setproperty(&mut self, property: &str, value: Value) -> BackendResult<()> {
+        self.command(serde_json::json!(["set_property", property, value]))?;
+        Ok(())
+    }
+
+    /// sets an mpv option now and for every track played after
+    ///
+    /// # arguments
+    /// * 'name' - name of the option, e.g. "replaygain"
+    /// * 'value' - the value as it would be written on the command line
+    ///
+    /// # returns
+    /// * none
+    fn setoption(&mut self, name: &str, value: &str) -> BackendResult<()>;
+
+    /// creates a second player with the same options that can play alongside this one
+    ///
+    /// # returns
+    /// * the new player, not playing anything yet
+    fn another(&self) -> Box<dyn Player>;
+
+    /// queues the track to play after the current one, so the player can move on without a gap
+    ///
+    /// replaces anything queued before. None only clears it
+    ///
+    /// # arguments
+    /// * 'url' - url or path of the next track
+    ///
+    /// # returns
+    /// * none
+    fn preload(&mut self, url: Option<&str>) -> BackendResult<()> {
+        self.command(serde_json::json!(["playlist-clear"]))?;
+        if let Some(url) = url {
+            self.command(serde_json::json!(["loadfile", url, "append"]))?;
+        }
+        Ok(())
+    }
+
+    /// checks whether the player moved on to the preloaded track, forgetting the old one if so
+    ///
+    /// # returns
+    /// * true if the preloaded track is playing now
+    fn advanced(&mut self) -> BackendResult<bool> {
+        let position = self.getproperty("playlist-pos")?.as_i64().unwrap_or(0);
+        if position > 0 {
+            self.command(serde_json::json!(["playlist-remove", 0]))?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// pauses if playing, plays if paused
+    fn togglepause(&mut self) -> BackendResult<()> {
+        self.command(serde_json::json!(["cycle", "pause"]))?;
+        Ok(())
+    }
+
+    /// gets the current playback position in seconds
+    fn position(&mut self) -> BackendResult<u32> {
+        let position = self.getproperty("time-pos")?;
+        Ok(position.as_f64().map(|p| p.floor() as u32).unwrap_or(0)) // convert to u32 (seconds)
+    }
+
+    /// gets the length of the current track in seconds, 0 while it is not known yet
+    fn duration(&mut self) -> BackendResult<u32> {
+        match self.getproperty("duration") {
+            Ok(duration) => Ok(duration.as_f64().map(|d| d.floor() as u32).unwrap_or(0)),
+            // "property unavailable" until the file is loaded
+            Err(BackendError::Mpv { .. }) => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// --- mpv ---
+
+/// process groups of the mpvs we spawned, 0 if none. two play at once while crossfading
+///
+/// kept outside of the player so the panic and signal paths can reach them
+static MPVPGIDS: [AtomicI32; 2] = [AtomicI32::new(0), AtomicI32::new(0)];
+
+/// PRIVATE function that forgets the process group of an mpv that is gone
+fn forgetpgid(pgid: i32) {
+    for slot in MPVPGIDS.iter() {
+        let _ = slot.compare_exchange(pgid, 0, Ordering::SeqCst, Ordering::SeqCst);
+    }
+}
+
+/// plays tracks with an mpv child process, controlled over its ipc socket
+#[derive(Debug)]
+pub struct MpvPlayer {
+    socket: PathBuf,                        // mpv's ipc socket
+    other: PathBuf,                         // socket of the player another() creates
+    slot: usize,                            // where in MPVPGIDS this player's mpv goes
+    args: Vec<String>,                      // extra flags from the config
+    options: BTreeMap<String, String>,      // options set while running, passed to every new mpv
+    child: Option<Child>,                   // mpv process
+    stderr: Arc<Mutex<VecDeque<String>>>,   // last lines mpv wrote to stderr
+}
+
+impl MpvPlayer {
+    pub fn new(socket: PathBuf, args: Vec<String>) -> Self {
+        let other = PathBuf::from(format!("{}-2", socket.display()));
+        MpvPlayer {
+            socket,
+            other,
+            slot: 0,
+            args,
+            options: BTreeMap::new(),
+            child: None,
+            stderr: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// PRIVATE function that keeps the last lines of mpv's stderr on a background thread
+    fn capturestderr(&self, stderr: ChildStderr) {
+        let lines = Arc::clone(&self.stderr);
+        if let Ok(mut lines) = lines.lock() {
+            lines.clear();
+        }
+
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(|l| l.ok()) {
+                if let Ok(mut lines) = lines.lock() {
+                    lines.push_back(line);
+                    if lines.len() > MAXSTDERRLINES {
+                        lines.pop_front();
+                    }
+                }
+            }
+        });
+    }
+}
+
+impl Player for MpvPlayer {
+    fn play(&mut self, url: &str, start: u32, paused: bool) -> BackendResult<()> {
+        self.stop();
+
+        // socket cleanup
+        let _ = std::fs::remove_file(&self.socket);
+
+        let mut command = Command::new("mpv");
+        command
+            .arg("--no-video")
+            .arg("--no-input-terminal")
+            .arg("--msg-level=all=error,statusline=no") // only errors reach stderr
+            .arg(format!("--input-ipc-server={}", self.socket.display()))
+            .arg("--keep-open=yes")
+            .arg(format!("--start={}", start))
+            .args(&self.args)
+            .args(self.options.iter().map(|(name, value)| format!("--{}={}", name, value)));
+        if paused {
+            command.arg("--pause");
+        }
+
+        command.arg(url);
+        log::info!("spawning {:?}", command);
+
+        let mut childproc = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())  // discard stdout
+            .stderr(Stdio::piped()) // keep errors for the crash popup
+            .process_group(0)       // own group, so ctrl-c in the terminal does not reach it and we can kill it whole
+            .spawn() // start the process
+            .map_err(|source| BackendError::Spawn { program: String::from("mpv"), url: String::from(url), source })?;
+        MPVPGIDS[self.slot].store(childproc.id() as i32, Ordering::SeqCst);
+        log::debug!("mpv spawned with pid {}", childproc.id());
+
+        if let Some(stderr) = childproc.stderr.take() {
+            self.capturestderr(stderr);
+        }
+        self.child = Some(childproc);
+
+        // give mpv a moment to create its socket so the first commands do not fail
+        for _ in 0..10 {
+            if self.socket.exists() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        if let Some(child) = self.child.take() {
+            killmpv(child);
+        }
+    }
+
+    fn isrunning(&self) -> bool {
+        self.child.is_some()
+    }
+
+    fn poll(&mut self) -> Option<PlayerExit> {
+        let status = self.child.as_mut()?.try_wait().ok().flatten()?;
+        let pgid = self.child.take().map(|c| c.id() as i32).unwrap_or(0);
+        forgetpgid(pgid);
+        log::info!("mpv exited on its own with {}", status);
+
+        if status.success() {
+            Some(PlayerExit::Finished)
+        } else {
+            Some(PlayerExit::Crashed)
+        }
+    }
+
+    fn setoption(&mut self, name: &str, value: &str) -> BackendResult<()> {
+        self.options.insert(String::from(name), String::from(value));
+        if self.isrunning() {
+            // "set" parses the value like the command line does
+            self.command(serde_json::json!(["set", name, value]))?;
+        }
+        Ok(())
+    }
+
+    fn another(&self) -> Box<dyn Player> {
+        // the two take turns, so a third one never needs a socket of its own
+        Box::new(MpvPlayer {
+            socket: self.other.clone(),
+            other: self.socket.clone(),
+            slot: 1 - self.slot,
+            args: self.args.clone(),
+            options: self.options.clone(),
+            child: None,
+            stderr: Arc::new(Mutex::new(VecDeque::new())),
+        })
+    }
+
+    fn errors(&self) -> Vec<String> {
+        self.stderr.lock().map(|lines| lines.iter().cloned().collect()).unwrap_or_default()
+    }
+
+    fn command(&mut self, command: Value) -> BackendResult<Value> {
+        let commandstr = command.to_string();
+        let ipcerror = |source: std::io::Error| BackendError::Ipc { command: commandstr.clone(), source };
+
+        // unique request ID for this request
+        let requestid = rand::random::<u32>();
+        let request = serde_json::json!({ "command": command, "request_id": requestid });
+
+        log::debug!("ipc -> {}", request);
+        let stream = UnixStream::connect(&self.socket).map_err(|e| {
+            log::debug!("ipc connect failed: {}", e);
+            ipcerror(e)
+        })?;
+        stream.set_read_timeout(Some(IPCTIMEOUT)).map_err(ipcerror)?;
+        stream.set_write_timeout(Some(IPCTIMEOUT)).map_err(ipcerror)?;
+        writeln!(&stream, "{}", request).map_err(ipcerror)?;
+
+        // mpv interleaves event messages with replies, skip until ours arrives
+        for line in BufReader::new(&stream).lines() {
+            let line = line.map_err(ipcerror)?;
+            log::debug!("ipc <- {}", line);
+            let reply: Value = serde_json::from_str(&line)
+                .map_err(|_| BackendError::BadReply { command: commandstr.clone(), reply: line.clone() })?;
+
+            if reply.get("request_id").and_then(|id| id.as_u64()) != Some(requestid as u64) {
+                continue;
+            }
+
+            return match reply.get("error").and_then(|e| e.as_str()) {
+                Some("success") => Ok(reply.get("data").cloned().unwrap_or(Value::Null)),
+                Some(error) => Err(BackendError::Mpv { command: commandstr, error: String::from(error) }),
+                None => Err(BackendError::BadReply { command: commandstr, reply: line }),
+            };
+        }
+
+        Err(ipcerror(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)))
+    }
+}
+
+/// kills an mpv child we spawned, along with anything it spawned (e.g. yt-dlp)
+///
+/// asks nicely first and only sends SIGKILL if mpv has not exited after half a second.
+/// mpv processes the user started themselves are never touched
+///
+/// # arguments
+/// * 'child' - the mpv child process
+///
+/// # returns
+/// * none
+pub fn killmpv(mut child: Child) {
+    let pgid = child.id() as i32;
+    unsafe { libc::killpg(pgid, libc::SIGTERM) };
+
+    for _ in 0..20 {
+        if let Ok(Some(status)) = child.try_wait() {
+            log::info!("mpv {} exited with {}", pgid, status);
+            forgetpgid(pgid);
+            return;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(25));
+    }
+
+    log::warn!("mpv {} ignored SIGTERM, sending SIGKILL", pgid);
+    unsafe { libc::killpg(pgid, libc::SIGKILL) };
+    let _ = child.wait();
+    forgetpgid(pgid);
+}
+
+/// kills the process groups of the mpvs we spawned without needing the app
+///
+/// used by the panic and signal cleanup paths, where the app may not be reachable
+///
+/// # arguments
+/// * none
+///
+/// # returns
+/// * none
+pub fn killmpvgroup() {
+    for slot in MPVPGIDS.iter() {
+        let pgid = slot.swap(0, Ordering::SeqCst);
+        if pgid > 0 {
+            log::warn!("killing mpv {} during cleanup", pgid);
+            unsafe { libc::killpg(pgid, libc::SIGKILL) };
+        }
+    }
+}
+
+// --- fake ---
+
+/// plays nothing and remembers everything, so playback logic can be tested without mpv
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct FakePlayer {
+    pub url: Option<String>,                                // what is "playing", None when stopped
+    pub preloaded: Option<String>,                          // what plays next
+    pub played: Vec<String>,                                // every url play was called with
+    pub paused: bool,
+    pub position: u32,
+    pub duration: u32,
+    pub properties: std::collections::HashMap<String, Value>, // anything else that was set
+    pub exit: Option<PlayerExit>,                           // returned by the next poll
+    pub errors: Vec<String>,
+}
+
+#[cfg(test)]
+impl Player for FakePlayer {
+    fn play(&mut self, url: &str, start: u32, paused: bool) -> BackendResult<()> {
+        self.url = Some(String::from(url));
+        self.preloaded = None;
+        self.played.push(String::from(url));
+        self.position = start;
+        self.paused = paused;
+        Ok(())
+    }
+
+    fn stop(&mut self) {
+        self.url = None;
+        self.preloaded = None;
+        self.position = 0;
+    }
+
+    fn isrunning(&self) -> bool {
+        self.url.is_some()
+    }
+
+    fn poll(&mut self) -> Option<PlayerExit> {
+        let exit = self.exit.take()?;
+        self.url = None;
+        Some(exit)
+    }
+
+    fn setoption(&mut self, name: &str, value: &str) -> BackendResult<()> {
+        self.properties.insert(String::from(name), Value::from(value));
+        Ok(())
+    }
+
+    fn another(&self) -> Box<dyn Player> {
+        Box::new(FakePlayer::default())
+    }
+
+    fn errors(&self) -> Vec<String> {
+        self.errors.clone()
+    }
+
+    fn command(&mut self, command: Value) -> BackendResult<Value> {
+        let commandstr = command.to_string();
+        if self.url.is_none() {
+            // like mpv's socket not being there
+            let source = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+            return Err(BackendError::Ipc { command: commandstr, source });
+        }
+
+        let args: Vec<Value> = command.as_array().cloned().unwrap_or_default();
+        let name = args.get(1).and_then(|n| n.as_str()).unwrap_or_default().to_string();
+        match args.first().and_then(|c| c.as_str()) {
+            Some("get_property") => match name.as_str() {
+                "pause" => Ok(Value::from(self.paused)),
+                "time-pos" => Ok(Value::from(self.position)),
+                "duration" if self.duration > 0 => Ok(Value::from(self.duration)),
+                "playlist-pos" => Ok(self.properties.get(&name).cloned().unwrap_or(Value::from(0))),
+                _ => self.properties.get(&name).cloned()
+                    .ok_or(BackendError::Mpv { command: commandstr, error: String::from("property unavailable") }),
+            },
+            Some("set_property") => {
+                let value = args.get(2).cloned().unwrap_or(Value::Null);
+                match name.as_str() {
+                    "pause" => self.paused = value.as_bool().unwrap_or(self.paused),
+                    "time-pos" => self.position = value.as_u64().unwrap_or(0) as u32,
+                    _ => { self.properties.insert(name, value); }
+                }
+                Ok(Value::Null)
+            }
+            Some("cycle") if name == "pause" => {
+                self.paused = !self.paused;
+                Ok(Value::Null)
+            }
+            Some("playlist-clear") => {
+                self.preloaded = None;
+                Ok(Value::Null)
+            }
+            Some("loadfile") => {
+                self.preloaded = Some(name);
+                Ok(Value::Null)
+            }
+            Some("playlist-remove") => {
+                // the preloaded track is the only one left, like mpv after a gapless transition
+                self.url = self.preloaded.take();
+                self.position = 0;
+                self.properties.remove("playlist-pos");
+                Ok(Value::Null)
+            }
+            _ => Ok(Value::Null),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use crate::consts::{App, StatusLevel};
+
+/// counts down to pausing, or quitting, so the music does not play all night
+#[derive(Debug, Default)]
+pub struct SleepTimer {
+    pub endsat: Option<Instant>,    // when to pause, None if the timer is off
+    pub presetidx: Option<usize>,   // preset the timer was last set to with the key
+}
+
+/// starts the sleep timer, replacing a running one
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'minutes' - how long until playback pauses, 0 turns the timer off
+/// 
+/// # returns
+/// * none
+pub fn start(app: &mut App, minutes: u32) {
+    if minutes == 0 {
+        cancel(app);
+        return;
+    }
+
+    app.sleep.endsat = Some(Instant::now() + Duration::from_secs(minutes as u64 * 60));
+    log::info!("sleep timer set for {} minutes", minutes);
+    app.status.push(StatusLevel::Info, format!("sleeping in {} minutes", minutes));
+}
+
+/// reads a sleep timer length typed into the palette
+/// 
+/// # arguments
+/// * 'input' - minutes ("45"), hours and minutes ("1:30") or "off"
+/// 
+/// # returns
+/// * the length in minutes, 0 for off, or None if it could not be read
+pub fn parseminutes(input: &str) -> Option<u32> {
+    if input.eq_ignore_ascii_case("off") {
+        return Some(0);
+    }
+    match input.split_once(':') {
+        Some((hours, minutes)) => Some(hours.trim().parse::<u32>().ok()? * 60 + minutes.trim().parse::<u32>().ok()?),
+        None => input.trim_end_matches('m').trim().parse().ok(),
+    }
+}
+
+/// turns the sleep timer off
+pub fn cancel(app: &mut App) {
+    if app.sleep.endsat.take().is_some() {
+        log::info!("sleep timer cancelled");
+        app.status.push(StatusLevel::Info, String::from("sleep timer off"));
+    }
+    app.sleep.presetidx = None;
+}
+
+/// moves the sleep timer to the next preset, turning it off after the last one
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn cyclepreset(app: &mut App) {
+    let next = match app.sleep.presetidx {
+        Some(idx) => idx + 1,
+        None => 0,
+    };
+    match app.config.sleeppresets.get(next).copied() {
+        Some(minutes) => {
+            start(app, minutes);
+            app.sleep.presetidx = Some(next);
+        }
+        None => cancel(app),
+    }
+}
+
+/// gets how long until the sleep timer goes off
+/// 
+/// # arguments
+/// * 'timer' - the sleep timer
+/// 
+/// # returns
+/// * the time left, None if the timer is off
+pub fn remaining(timer: &SleepTimer) -> Option<Duration> {
+    timer.endsat.map(|endsat| endsat.saturating_duration_since(Instant::now()))
+}
+
+/// gets how loud playback should be while the sleep timer fades out
+/// 
+/// # arguments
+/// * 'app' - reference to the app state
+/// 
+/// # returns
+/// * 1.0 outside of the fade, going down to 0.0 when the timer goes off
+pub fn volumefactor(app: &App) -> f64 {
+    match remaining(&app.sleep) {
+        Some(left) if app.config.sleepfade > 0 => (left.as_secs_f64() / app.config.sleepfade as f64).min(1.0),
+        _ => 1.0,
+    }
+}
+
+/// pauses, or quits, once the sleep timer goes off. called every tick
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn check(app: &mut App) -> Result<()> {
+    if remaining(&app.sleep) != Some(Duration::ZERO) {
+        return Ok(());
+    }
+
+    app.sleep = SleepTimer::default();
+    log::info!("sleep timer went off");
+    if app.playing {
+        app.player.togglepause()?;
+        app.playing = false;
+    }
+
+    // put the volume back so playing again is not silent
+    app.fadevolume = None;
+    if let Some(from) = app.fadefrom.take() {
+        app.player.setproperty("volume", serde_json::json!(from))?;
+    }
+
+    if app.config.sleepquits {
+        app.running = false;
+    } else {
+        app.status.push(StatusLevel::Info, String::from("sleep timer went off, paused"));
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+use std::collections::HashSet;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::consts::{unixtime, App, Playlist, PlaylistKind, Track};
+
+static DAYSECS: u64 = 24 * 60 * 60;
+
+/// one condition a track has to meet to be in a smart playlist
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rule {
+    ArtistContains(String),
+    TitleContains(String),
+    AlbumContains(String),
+    LongerThan(u32),        // seconds
+    ShorterThan(u32),       // seconds
+    PlayedMoreThan(u32),    // times
+    PlayedLessThan(u32),    // times
+    AddedWithin(u32),       // days
+    NotPlayedFor(u32),      // days, tracks that were never played count too
+    RatedAtLeast(u8),       // out of 5
+}
+
+/// PRIVATE function that parses a length like 300, 5:00 or 5m into seconds
+fn parselength(text: &str) -> Option<u32> {
+    if let Some(minutes) = text.strip_suffix('m') {
+        return minutes.trim().parse::<u32>().ok().map(|m| m * 60);
+    }
+    text.split(':').try_fold(0u32, |total, part| part.trim().parse::<u32>().ok().map(|p| total * 60 + p))
+}
+
+/// PRIVATE function that parses a number of days like 30 or 30d
+fn parsedays(text: &str) -> Option<u32> {
+    text.strip_suffix('d').unwrap_or(text).trim().parse().ok()
+}
+
+/// parses a query into rules
+///
+/// a query is a comma separated list of conditions that all have to match, like
+/// `artist ~ aerochord, length < 5:00, plays > 3, added < 30d, played > 90d`
+///
+/// # arguments
+/// * 'query' - the query
+///
+/// # returns
+/// * the rules, or an error naming the condition that could not be read
+pub fn parserules(query: &str) -> Result<Vec<Rule>> {
+    query.split(',').map(str::trim).filter(|c| !c.is_empty()).map(|condition| {
+        let invalid = || anyhow::anyhow!("could not read '{}', try something like 'plays > 3'", condition);
+        let opidx = condition.find(['~', '<', '>']).ok_or_else(invalid)?;
+        let field = condition[..opidx].trim().to_lowercase();
+        let (op, value) = match condition[opidx..].strip_prefix(">=") {
+            Some(value) => (">=", value.trim()),
+            None => (&condition[opidx..opidx + 1], condition[opidx + 1..].trim()),
+        };
+
+        let rule = match (field.as_str(), op) {
+            ("artist", "~") => Rule::ArtistContains(value.to_lowercase()),
+            ("title", "~") => Rule::TitleContains(value.to_lowercase()),
+            ("album", "~") => Rule::AlbumContains(value.to_lowercase()),
+            ("length", ">") => Rule::LongerThan(parselength(value).ok_or_else(invalid)?),
+            ("length", "<") => Rule::ShorterThan(parselength(value).ok_or_else(invalid)?),
+            ("plays", ">") => Rule::PlayedMoreThan(value.parse().map_err(|_| invalid())?),
+            ("plays", "<") => Rule::PlayedLessThan(value.parse().map_err(|_| invalid())?),
+            ("added", "<") => Rule::AddedWithin(parsedays(value).ok_or_else(invalid)?),
+            ("played", ">") => Rule::NotPlayedFor(parsedays(value).ok_or_else(invalid)?),
+            ("rating", ">=") => Rule::RatedAtLeast(value.parse().map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        };
+        Ok(rule)
+    }).collect()
+}
+
+/// checks if a track meets a rule
+///
+/// # arguments
+/// * 'rule' - the rule
+/// * 'track' - the track
+/// * 'now' - the current unix time, passed in so every track is judged at the same moment
+///
+/// # returns
+/// * whether it matches
+pub fn matches(rule: &Rule, track: &Track, now: u64) -> bool {
+    let contains = |text: &str, part: &str| text.to_lowercase().contains(part);
+    let agedays = |time: u64| now.saturating_sub(time) / DAYSECS;
+    match rule {
+        Rule::ArtistContains(part) => contains(&track.artist, part),
+        Rule::TitleContains(part) => contains(&track.title, part),
+        Rule::AlbumContains(part) => track.album.as_deref().is_some_and(|album| contains(album, part)),
+        // tracks without a known length match neither
+        Rule::LongerThan(secs) => track.duration > *secs,
+        Rule::ShorterThan(secs) => track.duration > 0 && track.duration < *secs,
+        Rule::PlayedMoreThan(times) => track.playcount > *times,
+        Rule::PlayedLessThan(times) => track.playcount < *times,
+        Rule::AddedWithin(days) => track.added.is_some_and(|added| agedays(added) < *days as u64),
+        Rule::NotPlayedFor(days) => track.lastplayed.is_none_or(|played| agedays(played) >= *days as u64),
+        Rule::RatedAtLeast(stars) => track.rating.is_some_and(|rating| rating >= *stars),
+    }
+}
+
+/// finds the tracks of the library that meet every rule
+///
+/// other smart playlists are not searched, and a track in several playlists is only listed once
+///
+/// # arguments
+/// * 'playlists' - the library
+/// * 'rules' - the rules
+///
+/// # returns
+/// * the tracks, in library order
+pub fn evaluate(playlists: &[Playlist], rules: &[Rule]) -> Vec<Track> {
+    let now = unixtime();
+    let mut seen: HashSet<&str> = HashSet::new();
+    playlists.iter()
+        .filter(|p| p.kind != PlaylistKind::Smart)
+        .flat_map(|p| p.tracks.iter())
+        .filter(|t| seen.insert(t.id.as_str()))
+        .filter(|t| rules.iter().all(|rule| matches(rule, t, now)))
+        .cloned()
+        .collect()
+}
+
+/// fills every smart playlist from the library, called whenever the library changes so they follow it
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+///
+/// # returns
+/// * none
+pub fn refresh(app: &mut App) {
+    for idx in 0..app.playlists.len() {
+        if app.playlists[idx].kind == PlaylistKind::Smart {
+            let tracks = evaluate(&app.playlists, &app.playlists[idx].rules);
+            app.playlists[idx].tracks = tracks;
+        }
+    }
+}
+
+/// creates a smart playlist
+///
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// * 'name' - name of the playlist
+/// * 'query' - the rules, see parserules
+///
+/// # returns
+/// * none
+pub fn create(app: &mut App, name: &str, query: &str) -> Result<()> {
+    let rules = parserules(query)?;
+    if rules.is_empty() {
+        return Err(anyhow::anyhow!("a smart playlist needs at least one rule"));
+    }
+    log::info!("creating smart playlist {} with {:?}", name, rules);
+    app.playlists.push(Playlist {
+        name: String::from(name),
+        kind: PlaylistKind::Smart,
+        rules,
+        ..Playlist::default()
+    });
+    refresh(app);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{entertrack, testapp};
+
+    #[test]
+    fn smartplaylistrulesareparsed() {
+        let rules = parserules("artist ~ Keys, length < 5:00, plays > 3, added < 30d, played > 90, rating >= 4").unwrap();
+        assert_eq!(rules, [
+            Rule::ArtistContains(String::from("keys")),
+            Rule::ShorterThan(300),
+            Rule::PlayedMoreThan(3),
+            Rule::AddedWithin(30),
+            Rule::NotPlayedFor(90),
+            Rule::RatedAtLeast(4),
+        ]);
+        assert_eq!(parserules("length > 10m").unwrap(), [Rule::LongerThan(600)]);
+        assert!(parserules("plays > lots").is_err());
+        assert!(parserules("colour ~ blue").is_err());
+    }
+
+    #[test]
+    fn smartplaylistsfollowthelibrary() {
+        let mut app = testapp(4);
+        app.playlists[0].tracks[0].artist = String::from("aerochord");
+        app.playlists[0].tracks[1].duration = 600;
+        app.playlists[0].tracks[2].lastplayed = Some(crate::consts::unixtime());
+        // the same track in another playlist is only listed once
+        let copy = app.playlists[0].tracks[3].clone();
+        app.playlists.push(Playlist { name: String::from("other"), tracks: vec![copy], ..Playlist::default() });
+
+        create(&mut app, "short and forgotten", "length < 5:00, played > 90d").unwrap();
+        let titles = |app: &App| app.playlists[2].tracks.iter().map(|t| t.title.clone()).collect::<Vec<String>>();
+        assert_eq!(titles(&app), ["track 0", "track 3"]);
+
+        // playing a track changes when it was last played, which refreshes the playlist
+        entertrack(&mut app, 0);
+        assert_eq!(titles(&app), ["track 3"]);
+    }
+}
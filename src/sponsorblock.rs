@@ -0,0 +1,259 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::consts::{unixtime, youtubeid, App, StatusLevel};
+use crate::paths;
+
+// every category is fetched so changing the config does not need new requests
+static CATEGORIES: [&str; 8] = ["sponsor", "selfpromo", "interaction", "intro", "outro", "preview", "music_offtopic", "filler"];
+static CACHESECS: u64 = 7 * 24 * 60 * 60;   // segments are refetched after a week, people keep submitting them
+static TIMEOUTSECS: u64 = 10;
+
+/// a part of a video to skip
+#[derive(PartialEq, Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Segment {
+    pub start: f64,         // seconds
+    pub end: f64,           // seconds
+    pub category: String,
+}
+
+/// segments of a video along with when they were fetched
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct CachedSegments {
+    fetched: u64,           // unix time
+    segments: Vec<Segment>,
+}
+
+/// fetches skip segments on a background thread
+#[derive(Debug)]
+pub struct Skipper {
+    cache: HashMap<String, CachedSegments>,                 // segments by video id
+    requests: Sender<(String, String)>,                     // api endpoints and video ids for the worker
+    results: Receiver<(String, Option<Vec<Segment>>)>,      // what the worker found
+    inflight: HashSet<String>,                              // video ids sent to the worker but not back yet
+    failed: HashSet<String>,                                // video ids that could not be fetched this session
+}
+
+impl Skipper {
+    /// creates a skipper with the on disk cache loaded and starts its worker thread
+    pub fn new() -> Self {
+        let (requests, workerrequests) = mpsc::channel::<(String, String)>();
+        let (workerresults, results) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (api, videoid) in workerrequests {
+                let segments = match fetch(&api, &videoid) {
+                    Ok(segments) => Some(segments),
+                    Err(e) => {
+                        log::warn!("could not get segments for {}: {}", videoid, e);
+                        None
+                    }
+                };
+                if workerresults.send((videoid, segments)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Skipper {
+            cache: loadcache(),
+            requests,
+            results,
+            inflight: HashSet::new(),
+            failed: HashSet::new(),
+        }
+    }
+}
+
+/// gets the path the segment cache is saved at
+pub fn cachepath() -> PathBuf {
+    paths::cachedir().join("segments.json")
+}
+
+/// PRIVATE function that loads the segment cache, starting empty if it is missing or broken
+fn loadcache() -> HashMap<String, CachedSegments> {
+    fs::read_to_string(cachepath())
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// saves the segment cache
+/// 
+/// # arguments
+/// * 'skipper' - the skipper whose cache to save
+/// 
+/// # returns
+/// * none
+pub fn savecache(skipper: &Skipper) -> Result<()> {
+    let path = cachepath();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string(&skipper.cache)?)?;
+    Ok(())
+}
+
+/// gets the skip segments of a video from a sponsorblock compatible api
+/// 
+/// # arguments
+/// * 'api' - base url of the server, like https://sponsor.ajay.app
+/// * 'videoid' - youtube video id
+/// 
+/// # returns
+/// * the segments sorted by start, empty if the video has none
+pub fn fetch(api: &str, videoid: &str) -> Result<Vec<Segment>> {
+    let url = format!("{}/api/skipSegments", api.trim_end_matches('/'));
+    log::debug!("fetching segments for {} from {}", videoid, url);
+
+    let response = ureq::get(&url)
+        .timeout(Duration::from_secs(TIMEOUTSECS))
+        .query("videoID", videoid)
+        .query("categories", &serde_json::to_string(&CATEGORIES)?)
+        .call();
+
+    let json: Value = match response {
+        Ok(response) => serde_json::from_reader(response.into_reader())?,
+        // the api answers 404 when a video has no segments
+        Err(ureq::Error::Status(404, _)) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut segments: Vec<Segment> = json
+        .as_array()
+        .map(|items| items.iter().filter_map(|item| {
+            // mute and highlight segments are not skipped
+            if item.get("actionType").and_then(|a| a.as_str()).is_some_and(|a| a != "skip") {
+                return None;
+            }
+            let bounds = item.get("segment")?.as_array()?;
+            Some(Segment {
+                start: bounds.first()?.as_f64()?,
+                end: bounds.get(1)?.as_f64()?,
+                category: item.get("category")?.as_str()?.to_string(),
+            })
+        }).collect())
+        .unwrap_or_default();
+
+    segments.sort_by(|a, b| a.start.total_cmp(&b.start));
+    Ok(segments)
+}
+
+/// stores finished lookups in the cache
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn update(app: &mut App) {
+    while let Ok((videoid, segments)) = app.sponsorblock.results.try_recv() {
+        app.sponsorblock.inflight.remove(&videoid);
+        match segments {
+            Some(segments) => {
+                log::info!("found {} segments for {}", segments.len(), videoid);
+                app.sponsorblock.cache.insert(videoid, CachedSegments { fetched: unixtime(), segments });
+            }
+            None => {
+                app.sponsorblock.failed.insert(videoid);
+            }
+        }
+    }
+}
+
+/// skips the segment the current track is in, called every second with the new position
+/// 
+/// segments are requested the first time a video plays, so the first few seconds
+/// of a new video may play before they arrive
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn skip(app: &mut App) -> Result<()> {
+    if !app.config.sponsorblock || !app.playing {
+        return Ok(());
+    }
+    let Some(videoid) = app.queue.get(app.currentqueueidx as usize).and_then(|t| youtubeid(&t.url)) else {
+        return Ok(());
+    };
+
+    let skipper = &mut app.sponsorblock;
+    let cached = skipper.cache.get(&videoid);
+    if cached.is_none_or(|c| unixtime().saturating_sub(c.fetched) > CACHESECS)
+        && !skipper.inflight.contains(&videoid)
+        && !skipper.failed.contains(&videoid)
+        && skipper.requests.send((app.config.sponsorblockapi.clone(), videoid.clone())).is_ok()
+    {
+        skipper.inflight.insert(videoid.clone());
+    }
+
+    let position = app.currentdurationsecs as f64;
+    let Some(segment) = cached.and_then(|c| c.segments.iter().find(|s| {
+        // a segment ending within the second is not worth a seek
+        app.config.sponsorblockcategories.contains(&s.category) && s.start <= position && position < s.end - 1.0
+    })).cloned() else {
+        return Ok(());
+    };
+
+    log::info!("skipping {} segment {:.1}-{:.1} of {}", segment.category, segment.start, segment.end, videoid);
+    app.player.command(serde_json::json!(["seek", segment.end, "absolute"]))?;
+    app.currentdurationsecs = segment.end as u32;
+    app.status.push(StatusLevel::Info, format!("skipped {}", segment.category.replace('_', " ")));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{entertrack, mockserver, testapp};
+
+    #[test]
+    fn sponsorblockskipsconfiguredcategories() {
+        let (url, server) = mockserver("200 OK", r#"[
+            {"segment": [2.5, 30.2], "category": "sponsor", "actionType": "skip"},
+            {"segment": [40, 50], "category": "intro", "actionType": "skip"},
+            {"segment": [60, 70], "category": "sponsor", "actionType": "mute"}
+        ]"#);
+        let mut app = testapp(1);
+        app.config.sponsorblock = true;
+        app.config.sponsorblockapi = url;
+        entertrack(&mut app, 0);
+        app.queue[0].url = String::from("https://www.youtube.com/watch?v=mpvlayertst");
+
+        app.currentdurationsecs = 5;
+        for _ in 0..100 {
+            update(&mut app);
+            skip(&mut app).unwrap();
+            if app.currentdurationsecs != 5 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(app.currentdurationsecs, 30);
+        assert!(server.join().unwrap().contains("videoID=mpvlayertst"));
+
+        // intro is not skipped by default and mute segments are never skipped
+        for position in [45, 65] {
+            app.currentdurationsecs = position;
+            skip(&mut app).unwrap();
+            assert_eq!(app.currentdurationsecs, position);
+        }
+    }
+
+    #[test]
+    fn sponsorblocknotfoundmeansnosegments() {
+        let (url, server) = mockserver("404 Not Found", "Not Found");
+        assert!(fetch(&url, "mpvlayertst").unwrap().is_empty());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use anyhow::Result;
+use crate::consts::App;
+use crate::{library, supervisor};
+
+/// reads the icy metadata of the current stream and notices when it drops, called every second
+/// 
+/// radio stations send the song that is on in icy-title and their own name in icy-name,
+/// which names stations that were added by their url alone. mpv is kept open at the end of
+/// a file, so a stream that dropped leaves it idle instead of exiting and it is reconnected here
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn update(app: &mut App) -> Result<()> {
+    let trackidx = app.currentqueueidx as usize;
+    if !app.playing || !app.player.isrunning() || !app.queue.get(trackidx).is_some_and(|t| t.islive()) {
+        return Ok(());
+    }
+
+    let dropped = ["eof-reached", "idle-active"]
+        .iter()
+        .any(|property| app.player.getproperty(property).ok().and_then(|v| v.as_bool()).unwrap_or(false));
+    if dropped {
+        app.player.stop();
+        return supervisor::ondrop(app);
+    }
+
+    let metadata = app.player.getproperty("metadata")?;
+    let field = |key: &str| metadata.get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+
+    let title = field("icy-title");
+    if title != app.streamtitle {
+        if let Some(title) = &title {
+            log::info!("stream is playing '{}'", title);
+        }
+        app.streamtitle = title;
+    }
+
+    if app.queue[trackidx].title.is_empty() {
+        if let Some(name) = field("icy-name") {
+            app.queue[trackidx].title = name.clone();
+            let trackid = app.queue[trackidx].id.clone();
+            library::updatetrack(&mut app.playlists, &trackid, |t| {
+                if t.title.is_empty() {
+                    t.title = name.clone();
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend;
+    use crate::testing::{entertrack, testapp};
+
+    #[test]
+    fn streamsshowicytitlesandpreloadnothing() {
+        let mut app = testapp(2);
+        app.playlists[0].tracks[0].source = crate::consts::SourceKind::Stream;
+        app.playlists[0].tracks[0].title = String::new();
+        entertrack(&mut app, 0);
+        assert_eq!(app.preloadedidx, None);
+
+        app.player.setproperty("metadata", serde_json::json!({"icy-name": "test fm", "icy-title": "artist - song"})).unwrap();
+        update(&mut app).unwrap();
+        assert_eq!(app.streamtitle.as_deref(), Some("artist - song"));
+        assert_eq!(app.playlists[0].tracks[0].title, "test fm");
+
+        backend::playnexttrack(&mut app).unwrap();
+        assert_eq!(app.streamtitle, None);
+    }
+
+    #[test]
+    fn droppedstreamsreconnect() {
+        let mut app = testapp(1);
+        app.playlists[0].tracks[0].source = crate::consts::SourceKind::Stream;
+        entertrack(&mut app, 0);
+
+        // mpv sits idle at the end of the stream instead of exiting
+        app.player.setproperty("eof-reached", serde_json::json!(true)).unwrap();
+        update(&mut app).unwrap();
+        assert!(!app.player.isrunning());
+        assert_eq!(app.supervisor.failures, 1);
+        assert!(app.supervisor.restartat.is_some());
+    }
+}
@@ -0,0 +1,277 @@
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use crate::{backend, bookmarks};
+use crate::constructors;
+use crate::consts::{App, StatusLevel};
+use crate::player::PlayerExit;
+
+static MAXRESTARTS: u32 = 3;            // restarts of one track before it is skipped
+static BACKOFFBASEMS: u64 = 1000;       // first restart delay, doubled on every failure
+static STABLESECS: u32 = 60;            // a stream that played this long before dropping starts counting again
+
+/// watches the player and restarts it when it crashes
+#[derive(Debug, Default)]
+pub struct Supervisor {
+    pub failures: u32,                          // crashes of the current track in a row
+    pub restartat: Option<Instant>,             // when to restart mpv after a crash
+    pub resumeat: u32,                          // position to resume from after a restart
+    pub ateof: bool,                            // mpv said it reached the end before it went away
+}
+
+/// forgets past crashes, used when a new track starts
+pub fn reset(supervisor: &mut Supervisor) {
+    supervisor.failures = 0;
+    supervisor.restartat = None;
+    supervisor.resumeat = 0;
+    supervisor.ateof = false;
+}
+
+/// gets how long to wait before the next restart
+/// 
+/// # arguments
+/// * 'failures' - crashes so far, at least 1
+/// 
+/// # returns
+/// * the delay, doubling with every failure
+pub fn backoff(failures: u32) -> Duration {
+    Duration::from_millis(BACKOFFBASEMS << failures.saturating_sub(1).min(10))
+}
+
+/// checks on mpv, called every tick
+/// 
+/// mpv keeps the file open at its end, so it exiting is the end of the track only if it
+/// reported eof-reached first, whatever its exit status. anything else is a crash,
+/// which is restarted at the last known position after a backoff. a track that keeps
+/// crashing is skipped and mpv's errors are shown in a popup
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn check(app: &mut App) -> Result<()> {
+    // --- did mpv exit? ---
+    if let Some(exit) = app.player.poll() {
+        if app.playing {
+            onexit(app, exit)?;
+        }
+    } else if app.player.isrunning() {
+        // it can not be asked once it is gone, so remember it now
+        app.supervisor.ateof = app.player.getproperty("eof-reached").ok().and_then(|v| v.as_bool()).unwrap_or(false);
+    }
+
+    // --- is a restart due? ---
+    if let Some(restartat) = app.supervisor.restartat {
+        if !app.playing {
+            app.supervisor.restartat = None;
+        } else if Instant::now() >= restartat && !app.player.isrunning() {
+            app.supervisor.restartat = None;
+            log::info!("restarting mpv at {}s", app.supervisor.resumeat);
+            let trackidx = app.currentqueueidx as usize;
+            if trackidx < app.queue.len() {
+                let resumeat = app.supervisor.resumeat;
+                let url = app.queue[trackidx].playurl().to_string();
+                match app.player.play(&url, resumeat, false) {
+                    Ok(()) => {
+                        app.currentdurationsecs = resumeat;
+                        app.fadefrom = None;
+                        app.fadevolume = None;
+                        if let Err(e) = bookmarks::applyabloop(app) {
+                            log::debug!("could not restore the loop: {}", e);
+                        }
+                        backend::preloadnext(app);
+                    }
+                    // failing to even spawn counts as another crash
+                    Err(_) => oncrash(app)?,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// PRIVATE function that handles mpv exiting
+fn onexit(app: &mut App, exit: PlayerExit) -> Result<()> {
+    let live = app.queue.get(app.currentqueueidx as usize).is_some_and(|t| t.islive());
+    if live {
+        // a stream never ends, it dropped
+        ondrop(app)
+    } else if app.supervisor.ateof {
+        // end of file, move on like the track finished
+        log::info!("mpv exited ({:?}) after the end of the track", exit);
+        reset(&mut app.supervisor);
+        app.currentdurationsecs = 0;
+        backend::trackended(app)
+    } else {
+        oncrash(app)
+    }
+}
+
+/// reconnects to a stream that dropped after a backoff, or stops if it stays down
+/// 
+/// # arguments
+/// * 'app' - mutable reference to the app state
+/// 
+/// # returns
+/// * none
+pub fn ondrop(app: &mut App) -> Result<()> {
+    if app.currentdurationsecs >= STABLESECS {
+        app.supervisor.failures = 0;
+    }
+    app.supervisor.failures += 1;
+    log::warn!("stream dropped, reconnect {} of {}", app.supervisor.failures, MAXRESTARTS);
+
+    if app.supervisor.failures > MAXRESTARTS {
+        let title = app.queue
+            .get(app.currentqueueidx as usize)
+            .map(|t| t.displaytitle().to_string())
+            .unwrap_or_default();
+        let mut message = vec![
+            format!("could not reconnect to {}", title),
+            String::new(),
+        ];
+        message.extend(app.player.errors());
+
+        app.status.push(StatusLevel::Error, format!("'{}' is not responding", title));
+        constructors::showpopup(app, String::from(" stream dropped "), message, false);
+        reset(&mut app.supervisor);
+        return backend::stop(app);
+    }
+
+    app.status.push(StatusLevel::Info, String::from("stream dropped, reconnecting"));
+    app.supervisor.resumeat = 0;
+    app.supervisor.restartat = Some(Instant::now() + backoff(app.supervisor.failures));
+    Ok(())
+}
+
+/// PRIVATE function that schedules a restart, or skips the track if it keeps crashing
+fn oncrash(app: &mut App) -> Result<()> {
+    app.supervisor.failures += 1;
+    log::warn!("mpv crashed, failure {} of {}", app.supervisor.failures, MAXRESTARTS + 1);
+
+    if app.supervisor.failures > MAXRESTARTS {
+        let title = app.queue
+            .get(app.currentqueueidx as usize)
+            .map(|t| t.displaytitle().to_string())
+            .unwrap_or_default();
+        let mut message = vec![
+            format!("mpv crashed {} times playing", MAXRESTARTS + 1),
+            title,
+            String::from("skipping to the next track"),
+            String::new(),
+        ];
+        message.extend(app.player.errors());
+
+        app.status.push(StatusLevel::Error, format!("skipped '{}' after mpv kept crashing", message[1]));
+        constructors::showpopup(app, String::from(" mpv crashed "), message, false);
+        reset(&mut app.supervisor);
+        return backend::trackended(app);
+    }
+
+    // only move the resume point forward, a crash right after a restart reports 0
+    app.supervisor.resumeat = std::cmp::max(app.supervisor.resumeat, app.currentdurationsecs);
+    app.supervisor.restartat = Some(Instant::now() + backoff(app.supervisor.failures));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::FakePlayer;
+    use crate::testing::{entertrack, testapp};
+
+    /// swaps in a player that is playing url at position and exits on the next poll
+    fn exitat(app: &mut App, position: u32, exit: PlayerExit) {
+        let url = app.queue[app.currentqueueidx as usize].playurl().to_string();
+        app.player = Box::new(FakePlayer { url: Some(url), position, exit: Some(exit), ..FakePlayer::default() });
+        app.currentdurationsecs = position;
+    }
+
+    /// lets a scheduled restart happen now
+    fn restartnow(app: &mut App) {
+        app.supervisor.restartat = Some(Instant::now());
+        check(app).unwrap();
+    }
+
+    #[test]
+    fn backoffdoubles() {
+        assert_eq!(backoff(1), Duration::from_millis(1000));
+        assert_eq!(backoff(2), Duration::from_millis(2000));
+        assert_eq!(backoff(3), Duration::from_millis(4000));
+        // capped so a long outage does not overflow
+        assert_eq!(backoff(100), backoff(11));
+    }
+
+    #[test]
+    fn acrashrestartsatthesavedposition() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 1);
+
+        exitat(&mut app, 42, PlayerExit::Crashed);
+        let before = Instant::now();
+        check(&mut app).unwrap();
+        assert_eq!(app.supervisor.failures, 1);
+        assert!(app.supervisor.restartat.is_some_and(|at| at >= before + backoff(1)));
+        assert!(!app.player.isrunning());
+
+        // the restart waits for the backoff
+        check(&mut app).unwrap();
+        assert!(!app.player.isrunning());
+
+        restartnow(&mut app);
+        assert!(app.player.isrunning());
+        assert_eq!(app.player.position().unwrap(), 42);
+        assert_eq!(app.currentqueueidx, 1);
+
+        // a crash right after the restart does not lose the position
+        exitat(&mut app, 0, PlayerExit::Crashed);
+        check(&mut app).unwrap();
+        assert_eq!(app.supervisor.failures, 2);
+        assert!(app.supervisor.restartat.is_some_and(|at| at >= Instant::now() + backoff(1)));
+        restartnow(&mut app);
+        assert_eq!(app.player.position().unwrap(), 42);
+    }
+
+    #[test]
+    fn atrackthatkeepscrashingisskipped() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 0);
+
+        for _ in 0..MAXRESTARTS {
+            exitat(&mut app, 10, PlayerExit::Crashed);
+            check(&mut app).unwrap();
+            restartnow(&mut app);
+            assert_eq!(app.currentqueueidx, 0);
+        }
+        exitat(&mut app, 10, PlayerExit::Crashed);
+        check(&mut app).unwrap();
+        assert_eq!(app.currentqueueidx, 1);
+        assert!(app.ui.popup.onscreen);
+        assert_eq!(app.supervisor.failures, 0);
+        assert!(app.supervisor.restartat.is_none());
+    }
+
+    #[test]
+    fn onlyanexitaftereofendsthetrack() {
+        let mut app = testapp(3);
+        entertrack(&mut app, 0);
+
+        // a clean exit in the middle of the track is still a crash
+        exitat(&mut app, 30, PlayerExit::Finished);
+        check(&mut app).unwrap();
+        assert_eq!(app.currentqueueidx, 0);
+        assert_eq!(app.supervisor.failures, 1);
+        restartnow(&mut app);
+
+        // mpv reached the end, then went away
+        app.player.setproperty("eof-reached", serde_json::json!(true)).unwrap();
+        check(&mut app).unwrap();
+        assert!(app.supervisor.ateof);
+        exitat(&mut app, 100, PlayerExit::Crashed);
+        check(&mut app).unwrap();
+        assert_eq!(app.currentqueueidx, 1);
+        assert_eq!(app.supervisor.failures, 0);
+        assert!(!app.ui.popup.onscreen);
+    }
+}
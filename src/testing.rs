@@ -0,0 +1,57 @@
+use ratatui_image::picker::Picker;
+use crate::app;
+use crate::config::Config;
+use crate::consts::{App, CurrentColumn, Playlist, Track};
+use crate::player::FakePlayer;
+
+/// creates an app with one playlist of tracks that plays through a fake player
+pub fn testapp(tracks: usize) -> App {
+    let tracks = (0..tracks)
+        .map(|i| Track::new(&format!("track {}", i), "artist", 100, &format!("https://example.com/{}", i)))
+        .collect();
+    let playlists = vec![Playlist { name: String::from("test"), tracks, ..Playlist::default() }];
+    App::new(playlists, Config::default(), Box::new(FakePlayer::default()), Picker::from_fontsize((8, 16)))
+}
+
+/// presses enter on a track in the tracks column
+pub fn entertrack(app: &mut App, idx: usize) {
+    app.ui.currentcolumn = CurrentColumn::Tracks;
+    app.ui.playliststate.select(Some(0));
+    app.ui.tracksstate.select(Some(idx));
+    app::handleenter(app).unwrap();
+}
+
+/// answers one http request with a json body, returning the request line
+pub fn mockserver(status: &str, body: &str) -> (String, std::thread::JoinHandle<String>) {
+    mockserverbytes(status, body.as_bytes())
+}
+
+/// answers one http request with any body, e.g. an image
+pub fn mockserverbytes(status: &str, body: &[u8]) -> (String, std::thread::JoinHandle<String>) {
+    use std::io::{BufRead, BufReader, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status, body.len()
+    ).into_bytes();
+    response.extend_from_slice(body);
+    let handle = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut requestline = String::new();
+        reader.read_line(&mut requestline).unwrap();
+        let mut line = String::new();
+        while reader.read_line(&mut line).unwrap() > 2 {
+            line.clear();
+        }
+        stream.write_all(&response).unwrap();
+        requestline
+    });
+    (url, handle)
+}
+
+/// reads a file from tests/fixtures
+pub fn fixture(name: &str) -> String {
+    std::fs::read_to_string(format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)).unwrap()
+}